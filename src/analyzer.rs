@@ -0,0 +1,585 @@
+//! Decoder of captured bus transactions, enabled by the `std` feature.
+//!
+//! A transaction is the list of (CMD, DAT) byte pairs sent while ATT was low.
+//! Captures of a logic analyzer are read by [`parse_capture`], one transaction per line
+//! of hex pairs `CMD:DAT`, for example `01:FF 42:41 00:5A 00:FF 00:FF`.
+use crate::buttons::{AnalogSticks, ButtonPressures, ButtonSet};
+use crate::commands::{
+    Command, DeviceMode, DeviceType, DATA_HEADER_SIZE, DATA_SIZE, MODE_ANALOG, MODE_LOCK, TX_PS2,
+};
+use crate::driver::DeviceBuffer;
+use crate::recording::{events, Event};
+use core::fmt;
+use std::format;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// Address of controller, the first byte of a packet
+const PAD_ADDRESS: u8 = 0x01;
+const PAYLOAD: usize = DATA_HEADER_SIZE as usize;
+
+/// Deviation from the protocol
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// The packet is not addressed to a controller
+    NotPadAddress(u8),
+    UnknownCommand(u8),
+    /// First byte of the response is not 0xFF, or ID is not a mode with 1, 3 or 9 words,
+    /// the driver reports [`ControllerError::BadHeader`](crate::Ps2ControllerError::BadHeader)
+    BadHeader,
+    /// Third byte of the response is not 0x5A
+    BadSignature(u8),
+    /// Command is valid in config mode only
+    NotInConfig,
+    /// Packet is shorter than the word count of ID
+    Truncated {
+        expected: usize,
+        received: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotPadAddress(v) => write!(f, "address 0x{v:02X} is not a pad"),
+            Self::UnknownCommand(v) => write!(f, "unknown command 0x{v:02X}"),
+            Self::BadHeader => write!(f, "bad header"),
+            Self::BadSignature(v) => write!(f, "signature 0x{v:02X} instead of 0x5A"),
+            Self::NotInConfig => write!(f, "config command outside config mode"),
+            Self::Truncated { expected, received } => {
+                write!(f, "truncated, {received} of {expected} bytes")
+            }
+        }
+    }
+}
+
+/// Meaning of the transaction by its command
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Details {
+    /// Poll or config command in normal mode, they answer the state of the pad
+    Poll {
+        buttons: ButtonSet,
+        sticks: Option<AnalogSticks>,
+        pressures: Option<ButtonPressures>,
+        /// Bytes 3 and 4 of CMD, values of motors if they are mapped
+        motors: [u8; 2],
+    },
+    Config {
+        enter: bool,
+    },
+    SetModeAndLock {
+        analog: bool,
+        locked: bool,
+    },
+    QueryModel {
+        model: DeviceType,
+        analog: bool,
+    },
+    /// Constants of GetConst1/2/3, index and 5 bytes
+    Constants {
+        index: u8,
+        data: [u8; 5],
+    },
+    MapMotors {
+        map: [u8; 6],
+    },
+    SetupPoll {
+        mask: [u8; 3],
+    },
+    /// Response of QueryDS2AnalogMode in config mode
+    PollMask {
+        mask: [u8; 5],
+    },
+    Unknown,
+}
+
+/// Decoded transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub pairs: Vec<(u8, u8)>,
+    pub address: u8,
+    /// Command byte, `Err` for unknown commands
+    pub command: Result<Command, u8>,
+    pub id: u8,
+    pub mode: DeviceMode,
+    pub words: u8,
+    pub details: Details,
+    pub violations: Vec<Violation>,
+}
+
+impl Transaction {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+    pub fn is_config(&self) -> bool {
+        0xF0 == self.id & 0xF0
+    }
+    /// One JSON object
+    pub fn to_json(&self) -> String {
+        let command = match self.command {
+            Ok(cmd) => format!("\"{cmd:?}\""),
+            Err(v) => format!("\"0x{v:02X}\""),
+        };
+        let hex = |bytes: &mut dyn Iterator<Item = u8>| {
+            let items: Vec<String> = bytes.map(|b| format!("\"{b:02X}\"")).collect();
+            format!("[{}]", items.join(","))
+        };
+        let violations: Vec<String> = self.violations.iter().map(|v| format!("\"{v}\"")).collect();
+        format!(
+            "{{\"address\":{},\"command\":{},\"id\":{},\"mode\":\"{:?}\",\"words\":{},\
+             \"cmd\":{},\"dat\":{},\"details\":{},\"violations\":[{}]}}",
+            self.address,
+            command,
+            self.id,
+            self.mode,
+            self.words,
+            hex(&mut self.pairs.iter().map(|p| p.0)),
+            hex(&mut self.pairs.iter().map(|p| p.1)),
+            details_json(&self.details),
+            violations.join(","),
+        )
+    }
+}
+
+fn details_json(details: &Details) -> String {
+    let list = |bytes: &[u8]| {
+        let items: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+        format!("[{}]", items.join(","))
+    };
+    match details {
+        Details::Poll {
+            buttons,
+            sticks,
+            pressures,
+            motors,
+        } => {
+            let names: Vec<String> = buttons.iter().map(|b| format!("\"{b:?}\"")).collect();
+            let sticks = match sticks {
+                Some(s) => format!(
+                    "{{\"lx\":{},\"ly\":{},\"rx\":{},\"ry\":{}}}",
+                    s.lx, s.ly, s.rx, s.ry
+                ),
+                None => String::from("null"),
+            };
+            let pressures = match pressures {
+                Some(p) => list(p.as_bytes()),
+                None => String::from("null"),
+            };
+            format!(
+                "{{\"poll\":{{\"buttons\":[{}],\"sticks\":{},\"pressures\":{},\"motors\":{}}}}}",
+                names.join(","),
+                sticks,
+                pressures,
+                list(motors)
+            )
+        }
+        Details::Config { enter } => format!("{{\"config\":{{\"enter\":{enter}}}}}"),
+        Details::SetModeAndLock { analog, locked } => {
+            format!("{{\"set_mode\":{{\"analog\":{analog},\"locked\":{locked}}}}}")
+        }
+        Details::QueryModel { model, analog } => {
+            format!("{{\"model\":{{\"type\":\"{model:?}\",\"analog\":{analog}}}}}")
+        }
+        Details::Constants { index, data } => {
+            format!(
+                "{{\"constants\":{{\"index\":{index},\"data\":{}}}}}",
+                list(data)
+            )
+        }
+        Details::MapMotors { map } => format!("{{\"map_motors\":{}}}", list(map)),
+        Details::SetupPoll { mask } => format!("{{\"setup_poll\":{}}}", list(mask)),
+        Details::PollMask { mask } => format!("{{\"poll_mask\":{}}}", list(mask)),
+        Details::Unknown => String::from("null"),
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.command {
+            Ok(cmd) => write!(f, "{cmd:?}")?,
+            Err(v) => write!(f, "0x{v:02X}")?,
+        }
+        write!(
+            f,
+            " id=0x{:02X} {:?} words={}",
+            self.id, self.mode, self.words
+        )?;
+        match &self.details {
+            Details::Poll {
+                buttons,
+                sticks,
+                pressures,
+                motors,
+            } => {
+                write!(f, " buttons=[")?;
+                for (i, btn) in buttons.iter().enumerate() {
+                    let sep = if i > 0 { " " } else { "" };
+                    write!(f, "{sep}{btn:?}")?;
+                }
+                write!(f, "]")?;
+                if let Some(s) = sticks {
+                    write!(f, " sticks=L({},{}) R({},{})", s.lx, s.ly, s.rx, s.ry)?;
+                }
+                if let Some(p) = pressures {
+                    write!(f, " pressures={:?}", p.as_bytes())?;
+                }
+                if motors.iter().any(|m| *m != 0) {
+                    write!(f, " motors={motors:02X?}")?;
+                }
+            }
+            Details::Config { enter } => write!(f, " enter={enter}")?,
+            Details::SetModeAndLock { analog, locked } => {
+                write!(f, " analog={analog} locked={locked}")?
+            }
+            Details::QueryModel { model, analog } => write!(f, " model={model:?} analog={analog}")?,
+            Details::Constants { index, data } => write!(f, " index={index} data={data:02X?}")?,
+            Details::MapMotors { map } => write!(f, " map={map:02X?}")?,
+            Details::SetupPoll { mask } => write!(f, " mask={mask:02X?}")?,
+            Details::PollMask { mask } => write!(f, " mask={mask:02X?}")?,
+            Details::Unknown => {}
+        }
+        for v in &self.violations {
+            write!(f, " !{v}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode one transaction of (CMD, DAT) pairs
+pub fn decode(pairs: &[(u8, u8)]) -> Transaction {
+    let mut cmd = [0u8; DATA_SIZE];
+    let mut buf = DeviceBuffer::default();
+    for (i, (c, d)) in pairs.iter().take(DATA_SIZE).enumerate() {
+        cmd[i] = *c;
+        buf.data[i] = *d;
+    }
+    let mut violations = Vec::new();
+    let address = cmd[0];
+    let command = Command::try_from(cmd[1]);
+    if PAD_ADDRESS != address {
+        violations.push(Violation::NotPadAddress(address));
+    }
+    if let Err(v) = command {
+        violations.push(Violation::UnknownCommand(v));
+    }
+    if !buf.rx_is_header_success() {
+        violations.push(Violation::BadHeader);
+    }
+    if TX_PS2 != buf.data[2] {
+        violations.push(Violation::BadSignature(buf.data[2]));
+    }
+    let is_config = 0xF0 == buf.rx_data_id();
+    let is_config_only = !matches!(
+        command,
+        Ok(Command::MainPoll | Command::Config | Command::QueryDS2AnalogMode) | Err(_)
+    );
+    if is_config_only && !is_config && buf.rx_is_any_mode() {
+        violations.push(Violation::NotInConfig);
+    }
+    let expected = PAYLOAD + buf.rx_data_len() as usize;
+    if buf.rx_is_header_success() && pairs.len() < expected {
+        violations.push(Violation::Truncated {
+            expected,
+            received: pairs.len(),
+        });
+    }
+    // missing bytes would be decoded as pressed buttons
+    let is_broken = violations
+        .iter()
+        .any(|v| matches!(v, Violation::BadHeader | Violation::Truncated { .. }));
+    let details = match is_broken {
+        true => Details::Unknown,
+        false => decode_details(command, is_config, &cmd, &buf),
+    };
+    Transaction {
+        pairs: pairs.to_vec(),
+        address,
+        command,
+        id: buf.data[1],
+        mode: DeviceMode::from(buf.data[1]),
+        words: buf.rx_data_word_count(),
+        details,
+        violations,
+    }
+}
+
+fn decode_details(
+    command: Result<Command, u8>,
+    is_config: bool,
+    cmd: &[u8; DATA_SIZE],
+    buf: &DeviceBuffer,
+) -> Details {
+    let tx = &cmd[PAYLOAD..];
+    let rx = &buf.data[PAYLOAD..];
+    let part = |from: usize| {
+        let mut data = [0u8; 5];
+        data.copy_from_slice(&rx[from..from + 5]);
+        data
+    };
+    match (command, is_config) {
+        (Ok(Command::MainPoll | Command::Config), false) => {
+            let details = Details::Poll {
+                buttons: ButtonSet::from_active_low(buf.rx_buttons()),
+                sticks: buf.rx_is_analog_mode().then(|| buf.rx_analog_sticks()),
+                pressures: buf.rx_pressures(),
+                motors: [tx[0], tx[1]],
+            };
+            match command {
+                Ok(Command::Config) if 0x01 == tx[0] => Details::Config { enter: true },
+                _ => details,
+            }
+        }
+        (Ok(Command::Config), true) => Details::Config {
+            enter: 0x01 == tx[0],
+        },
+        (Ok(Command::SetModeAndLock), true) => Details::SetModeAndLock {
+            analog: MODE_ANALOG == tx[0],
+            locked: MODE_LOCK == tx[1],
+        },
+        (Ok(Command::QueryModelAndMode), true) => Details::QueryModel {
+            model: DeviceType::from(buf.rx_data_model()),
+            analog: 0x01 == buf.rx_data_mode(),
+        },
+        (Ok(Command::GetConst1 | Command::GetConst2 | Command::GetConst3), true) => {
+            Details::Constants {
+                index: tx[0],
+                data: part(1),
+            }
+        }
+        (Ok(Command::MapMotors), true) => {
+            let mut map = [0u8; 6];
+            map.copy_from_slice(&tx[..6]);
+            Details::MapMotors { map }
+        }
+        (Ok(Command::SetupPoll), true) => Details::SetupPoll {
+            mask: [tx[0], tx[1], tx[2]],
+        },
+        (Ok(Command::QueryDS2AnalogMode), true) => Details::PollMask { mask: part(0) },
+        _ => Details::Unknown,
+    }
+}
+
+/// Streaming decoder, pairs are added until the end of transaction (ATT high)
+#[derive(Default)]
+pub struct Analyzer {
+    pairs: Vec<(u8, u8)>,
+    transactions: Vec<Transaction>,
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Decode a recording of [`RecordingTransport`](crate::recording::RecordingTransport),
+    /// transactions are split on the start and the end of packets
+    pub fn from_recording(data: &[u8]) -> Self {
+        let mut analyzer = Self::new();
+        for event in events(data) {
+            match event {
+                Event::Byte { cmd, dat, .. } => analyzer.push(cmd, dat),
+                Event::Start { .. } | Event::Stop => {
+                    analyzer.end();
+                }
+                Event::Error => {}
+            }
+        }
+        analyzer.end();
+        analyzer
+    }
+    pub fn push(&mut self, cmd: u8, dat: u8) {
+        self.pairs.push((cmd, dat));
+    }
+    /// End of transaction, returns it decoded
+    pub fn end(&mut self) -> Option<&Transaction> {
+        if self.pairs.is_empty() {
+            return None;
+        }
+        let transaction = decode(&self.pairs);
+        self.pairs.clear();
+        self.transactions.push(transaction);
+        self.transactions.last()
+    }
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+    /// Readable report, one transaction per line
+    pub fn report(&self) -> String {
+        let lines: Vec<String> = self.transactions.iter().map(|t| t.to_string()).collect();
+        lines.join("\n")
+    }
+    /// JSON array of transactions
+    pub fn to_json(&self) -> String {
+        let items: Vec<String> = self.transactions.iter().map(|t| t.to_json()).collect();
+        format!("[{}]", items.join(","))
+    }
+}
+
+/// Line of the capture which is not a list of hex pairs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+/// Read capture text, one transaction per line of `CMD:DAT` hex pairs,
+/// empty lines and lines starting with `#` are skipped
+pub fn parse_capture(text: &str) -> Result<Analyzer, ParseError> {
+    let mut analyzer = Analyzer::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        for pair in line.split_whitespace() {
+            let err = ParseError { line: n + 1 };
+            let (cmd, dat) = pair.split_once(':').ok_or(err)?;
+            let cmd = u8::from_str_radix(cmd, 16).map_err(|_| err)?;
+            let dat = u8::from_str_radix(dat, 16).map_err(|_| err)?;
+            analyzer.push(cmd, dat);
+        }
+        analyzer.end();
+    }
+    Ok(analyzer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::Button;
+    use crate::mock::{create_mock_controller, MockPad};
+    use crate::recording::RecordBuffer;
+    use std::vec;
+
+    const CAPTURE: &str = "\
+# digital, Start and Cross
+01:FF 42:41 00:5A 00:F7 00:BF
+01:FF 42:73 00:5A 00:FF 00:FF 00:80 00:7F 00:00 00:FF
+01:FF 42:79 00:5A 00:FF 00:DF 00:80 00:80 00:80 00:80 \
+00:00 00:00 00:00 00:00 00:00 00:C0 00:00 00:00 00:00 00:00 00:00 00:00
+01:FF 41:F3 00:5A 00:FF 00:FF 00:03 00:00 00:00 00:5A
+";
+
+    #[test]
+    fn polls() {
+        let analyzer = parse_capture(CAPTURE).unwrap();
+        let list = analyzer.transactions();
+        assert_eq!(4, list.len());
+        assert!(list.iter().all(Transaction::is_valid));
+        assert_eq!(
+            Details::Poll {
+                buttons: Button::Start | Button::Cross,
+                sticks: None,
+                pressures: None,
+                motors: [0, 0],
+            },
+            list[0].details
+        );
+        assert_eq!((DeviceMode::Analog, 3), (list[1].mode, list[1].words));
+        let Details::Poll { sticks, .. } = list[1].details else {
+            panic!("{:?}", list[1].details);
+        };
+        assert_eq!(Some(AnalogSticks::new(0x00, 0xFF, 0x80, 0x7F)), sticks);
+        let Details::Poll {
+            buttons, pressures, ..
+        } = list[2].details
+        else {
+            panic!("{:?}", list[2].details);
+        };
+        assert_eq!(ButtonSet::from(Button::Circle), buttons);
+        assert_eq!(0xC0, pressures.unwrap().get(Button::Circle));
+        // the whole answer of DualShock 2 after SetupPoll
+        assert_eq!(
+            Details::PollMask {
+                mask: [0xFF, 0xFF, 0x03, 0x00, 0x00]
+            },
+            list[3].details
+        );
+
+        let report = analyzer.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(
+            "MainPoll id=0x41 Digital words=1 buttons=[Start Cross]",
+            lines[0]
+        );
+        assert_eq!(
+            "MainPoll id=0x73 Analog words=3 buttons=[] sticks=L(0,255) R(128,127)",
+            lines[1]
+        );
+        assert_eq!(
+            "QueryDS2AnalogMode id=0xF3 DualShock2Native words=3 mask=[FF, FF, 03, 00, 00]",
+            lines[3]
+        );
+        let json = analyzer.to_json();
+        assert!(json.starts_with("[{\"address\":1,\"command\":\"MainPoll\",\"id\":65,"));
+        assert!(json.contains(
+            "\"details\":{\"poll\":{\"buttons\":[\"Start\",\"Cross\"],\"sticks\":null,\
+             \"pressures\":null,\"motors\":[0,0]}},\"violations\":[]}"
+        ));
+        assert!(json.contains("\"sticks\":{\"lx\":0,\"ly\":255,\"rx\":128,\"ry\":127}"));
+        assert!(json.contains("{\"poll_mask\":[255,255,3,0,0]}"));
+    }
+
+    #[test]
+    fn violations() {
+        let analyzer = parse_capture(
+            "01:FF 44:41 00:5A 01:FF 03:FF\n\
+             01:FF 42:73 00:5A 00:FF\n\
+             01:FF 42:FF 00:FF",
+        )
+        .unwrap();
+        let list = analyzer.transactions();
+        assert_eq!(vec![Violation::NotInConfig], list[0].violations);
+        assert_eq!(
+            vec![Violation::Truncated {
+                expected: 9,
+                received: 4
+            }],
+            list[1].violations
+        );
+        assert_eq!(Details::Unknown, list[1].details);
+        assert_eq!(
+            vec![Violation::BadHeader, Violation::BadSignature(0xFF)],
+            list[2].violations
+        );
+        assert_eq!(Details::Unknown, list[2].details);
+
+        let report = analyzer.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].ends_with(" !config command outside config mode"));
+        assert_eq!(
+            "MainPoll id=0x73 Analog words=3 !truncated, 4 of 9 bytes",
+            lines[1]
+        );
+        assert_eq!(
+            "MainPoll id=0xFF DualShock2Native words=15 !bad header !signature 0xFF instead of 0x5A",
+            lines[2]
+        );
+        let json = analyzer.to_json();
+        assert!(json.contains("\"details\":null,\"violations\":[\"truncated, 4 of 9 bytes\"]"));
+        assert!(json.contains("\"violations\":[\"bad header\",\"signature 0xFF instead of 0x5A\"]"));
+
+        assert_eq!(
+            Err(ParseError { line: 2 }),
+            parse_capture("01:FF\n01-FF").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_recording() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad).record(RecordBuffer::<4096>::new());
+        controller.connect();
+        controller.poll();
+        let analyzer = Analyzer::from_recording(controller.recorder().as_bytes());
+        let list = analyzer.transactions();
+        assert!(
+            list.iter().all(Transaction::is_valid),
+            "{}",
+            analyzer.report()
+        );
+        assert_eq!(Ok(Command::QueryDS2AnalogMode), list[0].command);
+        assert_eq!(Details::Config { enter: true }, list[1].details);
+        let last = list.last().unwrap();
+        assert_eq!(Ok(Command::MainPoll), last.command);
+        assert_eq!(DeviceMode::Digital, last.mode);
+    }
+}
@@ -0,0 +1,279 @@
+//! Async versions of the transport, driver and controller
+//! built on `embedded-hal-async`, enabled by the `async` feature.
+use crate::buttons::*;
+pub use crate::commands::ErrorType;
+use crate::commands::*;
+use crate::controller::Controller;
+use crate::device::{PsxDevice, SpiPsxDevice, TimingProfile};
+use crate::driver::{
+    device_info_packets, setup_packets, Action, DeviceBuffer, Exchange, Packet, Sequence,
+};
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::{delay::DelayNs, spi::SpiDevice};
+
+/// Creating an instance [`Controller`] to work with a device connected to ordinary digital pins
+#[allow(clippy::type_complexity)]
+pub fn create_psx_controller<Dat, Cmd, Att, Clk, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    delay: D,
+) -> Controller<Async<PsxDriver<PsxDevice<Dat, Cmd, Att, Clk, D>>>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    D: DelayNs,
+{
+    let dev = PsxDevice::new(dat, cmd, att, clk, delay, TimingProfile::default());
+    Controller::new(Async(PsxDriver::new(dev)))
+}
+
+/// Creating an instance [`Controller`] with the ACK pin connected, an absent device
+/// is detected by the missing acknowledge instead of a bad response
+#[allow(clippy::type_complexity)]
+pub fn create_psx_controller_with_ack<Dat, Cmd, Att, Clk, Ack, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    ack: Ack,
+    delay: D,
+) -> Controller<Async<PsxDriver<PsxDevice<Dat, Cmd, Att, Clk, D, Ack>>>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    Ack: InputPin,
+    D: DelayNs,
+{
+    let dev = PsxDevice::new(dat, cmd, att, clk, delay, TimingProfile::default()).with_ack(ack);
+    Controller::new(Async(PsxDriver::new(dev)))
+}
+
+/// Creating an instance [`Controller`] to work with a device connected to the SPI peripheral
+pub fn create_spi_controller<Spi, Att, D>(
+    spi: Spi,
+    att: Att,
+    delay: D,
+) -> Controller<Async<PsxDriver<SpiPsxDevice<Spi, Att, D>>>>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
+    D: DelayNs,
+{
+    let dev = SpiPsxDevice::new(spi, att, delay);
+    Controller::new(Async(PsxDriver::new(dev)))
+}
+
+/// Send commands and recieve data.
+/// Full-duplex protocol operating at 250 kHz, delays are awaited
+#[allow(async_fn_in_trait)]
+pub trait PsxTrasferData: DelayNs + ErrorType {
+    const SLEEP_NS: u32;
+
+    async fn start_cmd(&mut self) -> Result<(), Self::Error>;
+    async fn stop_cmd(&mut self) -> Result<(), Self::Error>;
+    async fn send_8bit(&mut self, byte: u8) -> Result<u8, Self::Error>;
+    async fn sleep(&mut self) {
+        self.delay_ns(Self::SLEEP_NS).await;
+    }
+    /// Acknowledge of the last byte, `Some(false)` - the device has finished the packet
+    /// or it is absent, `None` - the transport has no ACK line
+    fn last_ack(&self) -> Option<bool> {
+        None
+    }
+}
+
+/// Async version of the [`Driver`](crate::driver::Driver) trait
+#[allow(async_fn_in_trait)]
+pub trait Driver: ErrorType {
+    /// Initialize controller
+    async fn initialize(&mut self) -> CResult<(), Self::Error>;
+    /// Check presence of device by a single query, without waiting
+    async fn probe(&mut self) -> CResult<(), Self::Error>;
+    /// Polling controller state
+    async fn poll(&mut self) -> CResult<u16, Self::Error>;
+    /// Polling controller state and vibrate
+    async fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16, Self::Error>;
+    /// Get device status (mode, led)
+    async fn query_model_and_mode(&mut self) -> CResult<(u8, u8), Self::Error>;
+    /// Read device info
+    /// return 3 unknown consts, 10b, 5b and 10b sizes, and the poll mask
+    async fn read_device_info(&mut self) -> CResult<DeviceCInfo, Self::Error>;
+    /// Apply settings in config mode
+    async fn setup(&mut self, config: &DeviceConfig) -> CResult<(), Self::Error>;
+    /// Return analog sticks values
+    fn analog_sticks(&self) -> AnalogSticks;
+    /// Return button pressures, if the last response contains them
+    fn button_pressures(&self) -> Option<ButtonPressures>;
+    /// Read current device mode
+    fn current_mode(&self) -> DeviceMode;
+    /// Read ID of the last response
+    fn current_id(&self) -> u8;
+    /// Pause added to the pause between packets
+    fn set_extra_sleep(&mut self, ns: u32);
+}
+
+/// Async driver of [`Controller`], the wrapper selects the async methods of the controller.
+/// Any async [`Driver`] can be used: `Controller::new(Async(driver))`
+pub struct Async<D>(pub D);
+
+impl<D: ErrorType> ErrorType for Async<D> {
+    type Error = D::Error;
+}
+
+/// Implementation async [`Driver`] trait for async [`PsxTrasferData`] devices,
+/// packets are built and checked by the same code as in the blocking driver
+pub struct PsxDriver<Dev> {
+    exchange: Exchange,
+    dev: Dev,
+}
+
+impl<Dev: PsxTrasferData> PsxDriver<Dev> {
+    pub fn new(dev: Dev) -> Self {
+        Self {
+            exchange: Exchange::new(),
+            dev,
+        }
+    }
+    /// Transport of the driver
+    pub(crate) fn device(&self) -> &Dev {
+        &self.dev
+    }
+    pub(crate) fn device_mut(&mut self) -> &mut Dev {
+        &mut self.dev
+    }
+
+    /// Send one packet without pauses
+    async fn transfer(&mut self, packet: Packet) -> CResult<(), Dev::Error> {
+        self.dev
+            .start_cmd()
+            .await
+            .map_err(ControllerError::Transport)?;
+        self.exchange.begin(packet);
+        let result = self.exchange_bytes().await;
+        self.dev
+            .stop_cmd()
+            .await
+            .map_err(ControllerError::Transport)?;
+        Exchange::finish(packet, result)
+    }
+    async fn exchange_bytes(&mut self) -> CResult<(), Dev::Error> {
+        while let Some(byte) = self.exchange.next(self.dev.last_ack())? {
+            let rx = self
+                .dev
+                .send_8bit(byte)
+                .await
+                .map_err(ControllerError::Transport)?;
+            self.exchange.receive(rx)?;
+        }
+        Ok(())
+    }
+    /// Pause between packets, longer for models with quirks
+    async fn sleep(&mut self) {
+        self.dev.sleep().await;
+        if self.exchange.extra_sleep_ns() > 0 {
+            self.dev.delay_ns(self.exchange.extra_sleep_ns()).await;
+        }
+    }
+    /// Perform the actions of the sequence, `on_response` reads the response of every
+    /// packet passed to the sequence
+    async fn run<F>(
+        &mut self,
+        mut seq: Sequence<impl Iterator<Item = Packet>, Dev::Error>,
+        mut on_response: F,
+    ) -> CResult<(), Dev::Error>
+    where
+        F: FnMut(Packet, &DeviceBuffer),
+    {
+        loop {
+            match seq.next() {
+                Action::Sleep => self.sleep().await,
+                Action::Send(packet) => {
+                    let result = self.transfer(packet).await;
+                    if seq.complete(result) {
+                        on_response(packet, self.exchange.buffer());
+                    }
+                }
+                Action::Done => return seq.finish(),
+            }
+        }
+    }
+}
+
+impl<Dev: ErrorType> ErrorType for PsxDriver<Dev> {
+    type Error = Dev::Error;
+}
+
+impl<Dev> Driver for PsxDriver<Dev>
+where
+    Dev: PsxTrasferData,
+{
+    async fn initialize(&mut self) -> CResult<(), Dev::Error> {
+        self.run(Sequence::wait_response(10), |_, _| {}).await
+    }
+
+    async fn probe(&mut self) -> CResult<(), Dev::Error> {
+        self.run(Sequence::probe(), |_, _| {}).await
+    }
+
+    async fn poll(&mut self) -> CResult<u16, Dev::Error> {
+        self.transfer(Packet::Poll).await?;
+        Ok(self.exchange.buffer().rx_buttons())
+    }
+
+    async fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16, Dev::Error> {
+        self.transfer(Packet::PollVibrate(m1, m2)).await?;
+        Ok(self.exchange.buffer().rx_buttons())
+    }
+
+    async fn query_model_and_mode(&mut self) -> CResult<(u8, u8), Dev::Error> {
+        let mut model = (0, 0);
+        self.run(
+            Sequence::configure([Packet::QueryModel].into_iter()),
+            |_, buf| {
+                model = (buf.rx_data_model(), buf.rx_data_mode());
+            },
+        )
+        .await?;
+        Ok(model)
+    }
+
+    async fn read_device_info(&mut self) -> CResult<DeviceCInfo, Dev::Error> {
+        let mut info = DeviceCInfo::default();
+        self.run(Sequence::configure(device_info_packets()), |packet, buf| {
+            info.read_part(packet, buf)
+        })
+        .await?;
+        Ok(info)
+    }
+
+    async fn setup(&mut self, config: &DeviceConfig) -> CResult<(), Dev::Error> {
+        self.run(Sequence::configure(setup_packets(config)), |_, _| {})
+            .await
+    }
+
+    fn analog_sticks(&self) -> AnalogSticks {
+        self.exchange.analog_sticks()
+    }
+
+    fn button_pressures(&self) -> Option<ButtonPressures> {
+        self.exchange.button_pressures()
+    }
+
+    fn current_mode(&self) -> DeviceMode {
+        self.exchange.current_mode()
+    }
+
+    fn current_id(&self) -> u8 {
+        self.exchange.current_id()
+    }
+
+    fn set_extra_sleep(&mut self, ns: u32) {
+        self.exchange.set_extra_sleep(ns);
+    }
+}
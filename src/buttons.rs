@@ -1,7 +1,7 @@
-use core::ops::{BitAnd, BitOr};
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, Not, Sub};
 
 #[repr(u16)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Button {
     Select = 0x0001,
     LJoyBtn = 0x0002,
@@ -22,67 +22,295 @@ pub enum Button {
     Circle = 0x2000,
     Cross = 0x4000,
     Square = 0x8000,
-
-    All = 0xFFFF,
-    Arrows = 0x00F0,
 }
 
+/// Single buttons in order of bits
+pub(crate) const BUTTONS: [Button; 16] = [
+    Button::Select,
+    Button::LJoyBtn,
+    Button::RJoyBtn,
+    Button::Start,
+    Button::Up,
+    Button::Right,
+    Button::Down,
+    Button::Left,
+    Button::LTrigger,
+    Button::RTrigger,
+    Button::LButton,
+    Button::RButton,
+    Button::Triangle,
+    Button::Circle,
+    Button::Cross,
+    Button::Square,
+];
+
 impl BitOr<Button> for Button {
-    type Output = u16;
+    type Output = ButtonSet;
     fn bitor(self, rhs: Self) -> Self::Output {
-        (self as u16) | (rhs as u16)
+        ButtonSet::from(self) | rhs
+    }
+}
+impl BitOr<ButtonSet> for Button {
+    type Output = ButtonSet;
+    fn bitor(self, rhs: ButtonSet) -> Self::Output {
+        ButtonSet::from(self) | rhs
+    }
+}
+impl BitAnd<Button> for Button {
+    type Output = ButtonSet;
+    fn bitand(self, rhs: Button) -> Self::Output {
+        ButtonSet::from(self) & rhs
     }
 }
-impl BitOr<u16> for Button {
-    type Output = u16;
-    fn bitor(self, rhs: u16) -> Self::Output {
-        (self as u16) | rhs
+impl BitAnd<ButtonSet> for Button {
+    type Output = ButtonSet;
+    fn bitand(self, rhs: ButtonSet) -> Self::Output {
+        ButtonSet::from(self) & rhs
     }
 }
-impl BitOr<Button> for u16 {
-    type Output = u16;
-    fn bitor(self, rhs: Button) -> Self::Output {
-        self | (rhs as u16)
+impl Not for Button {
+    type Output = ButtonSet;
+    fn not(self) -> Self::Output {
+        !ButtonSet::from(self)
+    }
+}
+impl From<Button> for u16 {
+    fn from(v: Button) -> u16 {
+        v as u16
     }
 }
 
-impl BitAnd<Button> for Button {
-    type Output = u16;
-    fn bitand(self, _rhs: Button) -> Self::Output {
-        0x0000
+/// Set of buttons, a set bit is a button in the set
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonSet(u16);
+
+impl ButtonSet {
+    pub const EMPTY: Self = Self(0x0000);
+    pub const ALL: Self = Self(0xFFFF);
+    pub const ARROWS: Self = Self::of(&[Button::Up, Button::Right, Button::Down, Button::Left]);
+    pub const SHAPES: Self = Self::of(&[
+        Button::Triangle,
+        Button::Circle,
+        Button::Cross,
+        Button::Square,
+    ]);
+    pub const SHOULDERS: Self = Self::of(&[
+        Button::LTrigger,
+        Button::RTrigger,
+        Button::LButton,
+        Button::RButton,
+    ]);
+
+    pub const fn new() -> Self {
+        Self::EMPTY
+    }
+    /// Set of the listed buttons
+    pub const fn of(buttons: &[Button]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < buttons.len() {
+            bits |= buttons[i] as u16;
+            i += 1;
+        }
+        Self(bits)
+    }
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+    /// Pressed buttons of the device response, where pressed buttons are 0
+    pub(crate) const fn from_active_low(buttons: u16) -> Self {
+        Self(!buttons)
+    }
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+    pub const fn with(self, btn: Button) -> Self {
+        Self(self.0 | btn as u16)
+    }
+    pub const fn without(self, btn: Button) -> Self {
+        Self(self.0 & !(btn as u16))
+    }
+    pub const fn contains(self, btn: Button) -> bool {
+        (self.0 & btn as u16) != 0
+    }
+    /// At least one button of `other` is in the set
+    pub const fn any(self, other: ButtonSet) -> bool {
+        (self.0 & other.0) != 0
+    }
+    /// All buttons of `other` are in the set
+    pub const fn all(self, other: ButtonSet) -> bool {
+        (self.0 & other.0) == other.0
+    }
+    pub const fn union(self, other: ButtonSet) -> Self {
+        Self(self.0 | other.0)
+    }
+    pub const fn intersection(self, other: ButtonSet) -> Self {
+        Self(self.0 & other.0)
+    }
+    pub const fn difference(self, other: ButtonSet) -> Self {
+        Self(self.0 & !other.0)
+    }
+    /// Buttons in one of the sets only
+    pub const fn symmetric_difference(self, other: ButtonSet) -> Self {
+        Self(self.0 ^ other.0)
+    }
+    pub const fn complement(self) -> Self {
+        Self(!self.0)
+    }
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+    /// Buttons of the set in order of bits
+    pub fn iter(self) -> ButtonSetIter {
+        ButtonSetIter {
+            set: self,
+            index: 0,
+        }
     }
 }
-impl BitAnd<u16> for Button {
-    type Output = u16;
-    fn bitand(self, rhs: u16) -> Self::Output {
-        (self as u16) & rhs
+
+impl From<Button> for ButtonSet {
+    fn from(btn: Button) -> Self {
+        Self(btn as u16)
     }
 }
-impl PartialEq<u16> for Button {
-    fn eq(&self, rhs: &u16) -> bool {
-        (*self as u16) == *rhs
+impl<T: Into<ButtonSet>> BitOr<T> for ButtonSet {
+    type Output = ButtonSet;
+    fn bitor(self, rhs: T) -> Self::Output {
+        self.union(rhs.into())
     }
 }
-impl PartialEq<Button> for u16 {
-    fn eq(&self, rhs: &Button) -> bool {
-        (*self as u16) == *rhs as u16
+impl<T: Into<ButtonSet>> BitOrAssign<T> for ButtonSet {
+    fn bitor_assign(&mut self, rhs: T) {
+        *self = self.union(rhs.into());
     }
 }
-impl From<Button> for u16 {
-    fn from(v: Button) -> u16 {
-        v as u16
+impl<T: Into<ButtonSet>> BitAnd<T> for ButtonSet {
+    type Output = ButtonSet;
+    fn bitand(self, rhs: T) -> Self::Output {
+        self.intersection(rhs.into())
+    }
+}
+impl<T: Into<ButtonSet>> BitAndAssign<T> for ButtonSet {
+    fn bitand_assign(&mut self, rhs: T) {
+        *self = self.intersection(rhs.into());
+    }
+}
+impl<T: Into<ButtonSet>> BitXor<T> for ButtonSet {
+    type Output = ButtonSet;
+    fn bitxor(self, rhs: T) -> Self::Output {
+        self.symmetric_difference(rhs.into())
+    }
+}
+impl<T: Into<ButtonSet>> Sub<T> for ButtonSet {
+    type Output = ButtonSet;
+    fn sub(self, rhs: T) -> Self::Output {
+        self.difference(rhs.into())
+    }
+}
+impl Not for ButtonSet {
+    type Output = ButtonSet;
+    fn not(self) -> Self::Output {
+        self.complement()
+    }
+}
+impl FromIterator<Button> for ButtonSet {
+    fn from_iter<I: IntoIterator<Item = Button>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::EMPTY, |set, btn| set.with(btn))
+    }
+}
+impl IntoIterator for ButtonSet {
+    type Item = Button;
+    type IntoIter = ButtonSetIter;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over buttons of [`ButtonSet`]
+pub struct ButtonSetIter {
+    set: ButtonSet,
+    index: usize,
+}
+impl Iterator for ButtonSetIter {
+    type Item = Button;
+    fn next(&mut self) -> Option<Button> {
+        while self.index < BUTTONS.len() {
+            let btn = BUTTONS[self.index];
+            self.index += 1;
+            if self.set.contains(btn) {
+                return Some(btn);
+            }
+        }
+        None
+    }
+}
+
+/// Buttons of the last two polls as answered by device, pressed buttons are 0.
+/// Bits are [`Button`] or [`GuitarButton`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ButtonState {
+    current: u16,
+    prev: u16,
+}
+impl ButtonState {
+    pub(crate) const RELEASED: Self = Self {
+        current: 0xFFFF,
+        prev: 0xFFFF,
+    };
+
+    pub(crate) fn update(&mut self, buttons: u16) {
+        self.prev = self.current;
+        self.current = buttons;
+    }
+    /// All buttons up, the last poll becomes the previous one
+    pub(crate) fn release(&mut self) {
+        self.update(Self::RELEASED.current);
+    }
+    /// Buttons of the last poll in the device encoding
+    pub(crate) fn raw(&self) -> u16 {
+        self.current
+    }
+    pub(crate) fn pressed(&self) -> ButtonSet {
+        ButtonSet::from_active_low(self.current)
+    }
+    pub(crate) fn pressed_prev(&self) -> ButtonSet {
+        ButtonSet::from_active_low(self.prev)
+    }
+    /// Any button is pressed
+    pub(crate) fn is_active(&self) -> bool {
+        self.current != Self::RELEASED.current
+    }
+    pub(crate) fn is_changed(&self, bits: u16) -> bool {
+        ((self.prev ^ self.current) & bits) > 0
+    }
+    pub(crate) fn is_pressed(&self, bits: u16) -> bool {
+        (!self.current & bits) > 0
+    }
+    pub(crate) fn is_down(&self, bits: u16) -> bool {
+        self.is_changed(bits) && self.is_pressed(bits)
+    }
+    pub(crate) fn is_up(&self, bits: u16) -> bool {
+        self.is_changed(bits) && (!self.prev & bits) > 0
     }
 }
 
-#[allow(dead_code)]
+/// Buttons of Guitar Hero controller, bits of the same response as [`Button`]
 #[repr(u16)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum GuitarButton {
     UpStrum = 0x0010,
     RightStrum = 0x0020,
     DownStrum = 0x0040,
+    /// Always down, it identifies the guitar
     LeftStrum = 0x0080,
-    StartPower = 0x0100,
+    /// Star power, activated by tilt of the guitar
+    StarPower = 0x0100,
     GreenFret = 0x0200,
     YellowFret = 0x1000,
     RedFret = 0x2000,
@@ -90,7 +318,26 @@ pub enum GuitarButton {
     OrangeFret = 0x8000,
 }
 
-#[derive(Copy, Clone)]
+/// Frets in order from the head of the guitar
+pub(crate) const GUITAR_FRETS: [GuitarButton; 5] = [
+    GuitarButton::GreenFret,
+    GuitarButton::RedFret,
+    GuitarButton::YellowFret,
+    GuitarButton::BlueFret,
+    GuitarButton::OrangeFret,
+];
+
+/// Position of the strum bar
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strum {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AnalogSticks {
     pub lx: u8,
     pub ly: u8,
@@ -107,3 +354,54 @@ impl Default for AnalogSticks {
         Self::new(128, 128, 128, 128)
     }
 }
+
+/// Buttons reporting their pressure, in order of the device response
+const PRESSURE_BUTTONS: [Button; 12] = [
+    Button::Right,
+    Button::Left,
+    Button::Up,
+    Button::Down,
+    Button::Triangle,
+    Button::Circle,
+    Button::Cross,
+    Button::Square,
+    Button::LButton,
+    Button::RButton,
+    Button::LTrigger,
+    Button::RTrigger,
+];
+
+/// Pressure of the 12 pressure sensitive buttons (DualShock 2 only)
+/// 0 - released, 255 - fully pressed
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonPressures {
+    data: [u8; 12],
+}
+impl ButtonPressures {
+    pub fn new(data: [u8; 12]) -> Self {
+        Self { data }
+    }
+    /// Digital fallback, 255 for pressed buttons
+    pub fn from_buttons(pressed: ButtonSet) -> Self {
+        let mut data = [0u8; 12];
+        for (i, btn) in PRESSURE_BUTTONS.iter().enumerate() {
+            if pressed.contains(*btn) {
+                data[i] = 0xFF;
+            }
+        }
+        Self { data }
+    }
+    /// Pressure of button, 0 for buttons without pressure sensor
+    pub fn get(&self, btn: Button) -> u8 {
+        PRESSURE_BUTTONS
+            .iter()
+            .position(|b| *b == btn)
+            .map_or(0, |i| self.data[i])
+    }
+    /// Raw values in order of the device response
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        &self.data
+    }
+}
@@ -0,0 +1,182 @@
+use crate::commands::{DeviceCInfo, DeviceMode, DeviceType};
+
+/// Actuators and modes read while connecting
+pub const MAX_ACTUATORS: usize = 2;
+pub const MAX_MODES: usize = 2;
+
+/// Function of the actuator, byte 2 of `GetConst1` is function, byte 3 is subfunction
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ActuatorType {
+    #[default]
+    None,
+    /// Vibration motor switched on and off by one bit
+    SmallMotor,
+    /// Vibration motor with the speed of 8 bits
+    LargeMotor,
+    Unknown,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Actuator {
+    pub kind: ActuatorType,
+    /// Current drawn by the actuator
+    pub current_ma: u16,
+}
+
+impl Actuator {
+    fn from_part(part: &[u8]) -> Self {
+        let kind = match (part[1], part[2]) {
+            (0x00, _) => ActuatorType::None,
+            (0x01, 0x01) => ActuatorType::LargeMotor,
+            (0x01, 0x02) => ActuatorType::SmallMotor,
+            _ => ActuatorType::Unknown,
+        };
+        Self {
+            kind,
+            current_ma: part[4] as u16 * 10,
+        }
+    }
+}
+
+/// Decoded [`DeviceCInfo`]: actuators, supported modes and pressures.
+/// DualShock 2 has two motors, digital and analog modes and pressures,
+/// DualShock has no pressures, clones often answer zeros
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceCapabilities {
+    actuators: [Actuator; MAX_ACTUATORS],
+    actuator_count: u8,
+    modes: [DeviceMode; MAX_MODES],
+    mode_count: u8,
+    is_pressures: bool,
+}
+
+impl Default for DeviceCapabilities {
+    fn default() -> Self {
+        Self {
+            actuators: [Actuator::default(); MAX_ACTUATORS],
+            actuator_count: 0,
+            modes: [DeviceMode::Unknown; MAX_MODES],
+            mode_count: 0,
+            is_pressures: false,
+        }
+    }
+}
+
+impl DeviceCapabilities {
+    pub fn actuators(&self) -> &[Actuator] {
+        &self.actuators[..self.actuator_count as usize]
+    }
+    pub fn actuator_count(&self) -> u8 {
+        self.actuator_count
+    }
+    pub fn modes(&self) -> &[DeviceMode] {
+        &self.modes[..self.mode_count as usize]
+    }
+    pub fn mode_count(&self) -> u8 {
+        self.mode_count
+    }
+    pub fn is_mode_supported(&self, mode: DeviceMode) -> bool {
+        self.modes().contains(&mode)
+    }
+    /// Device answers the poll mask of 18 bytes, button pressures can be enabled
+    pub fn is_pressures(&self) -> bool {
+        self.is_pressures
+    }
+    /// DualShock 2 has pressures in any mode, byte 3 of `QueryModelAndMode`
+    pub(crate) fn with_model(mut self, model: u8) -> Self {
+        self.is_pressures |= DeviceType::DualShock2 as u8 == model;
+        self
+    }
+}
+
+impl From<&DeviceCInfo> for DeviceCapabilities {
+    fn from(info: &DeviceCInfo) -> Self {
+        let mut caps = Self::default();
+        for part in info.unknown1.chunks(5) {
+            let actuator = Actuator::from_part(part);
+            if ActuatorType::None != actuator.kind {
+                caps.actuators[caps.actuator_count as usize] = actuator;
+                caps.actuator_count += 1;
+            }
+        }
+        // upper nibble of ID in byte 3 of `GetConst3`
+        for part in info.unknown3.chunks(5) {
+            if 0x00 != part[2] {
+                caps.modes[caps.mode_count as usize] = DeviceMode::from(part[2] << 4);
+                caps.mode_count += 1;
+            }
+        }
+        // the third byte of mask, bits of pressure bytes 16 and 17, zeros in digital mode
+        caps.is_pressures = 0x03 == info.poll_mask[1] & 0x03;
+        caps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Responses of SCPH-10010 in config mode to `DEVICE_INFO_PARTS`
+    const DS2_RESPONSES: [[u8; 9]; 6] = [
+        [0xFF, 0xF3, 0x5A, 0x00, 0x00, 0x01, 0x02, 0x00, 0x0A],
+        [0xFF, 0xF3, 0x5A, 0x00, 0x00, 0x01, 0x01, 0x01, 0x14],
+        [0xFF, 0xF3, 0x5A, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00],
+        [0xFF, 0xF3, 0x5A, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00],
+        [0xFF, 0xF3, 0x5A, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00],
+        [0xFF, 0xF3, 0x5A, 0xFF, 0xFF, 0x03, 0x00, 0x00, 0x5A],
+    ];
+
+    fn info(responses: &[[u8; 9]; 6]) -> DeviceCInfo {
+        let mut info = DeviceCInfo::default();
+        for (n, response) in responses.iter().enumerate() {
+            info.set_part(n, &response[4..]);
+        }
+        info
+    }
+
+    #[test]
+    fn dualshock2_constants() {
+        let caps = DeviceCapabilities::from(&info(&DS2_RESPONSES));
+        assert_eq!(
+            [
+                Actuator {
+                    kind: ActuatorType::SmallMotor,
+                    current_ma: 100
+                },
+                Actuator {
+                    kind: ActuatorType::LargeMotor,
+                    current_ma: 200
+                },
+            ],
+            caps.actuators()
+        );
+        assert_eq!([DeviceMode::Digital, DeviceMode::Analog], caps.modes());
+        assert!(caps.is_mode_supported(DeviceMode::Analog));
+        assert!(!caps.is_mode_supported(DeviceMode::DualShock2Native));
+        assert!(caps.is_pressures());
+    }
+
+    #[test]
+    fn pressures_by_model() {
+        // poll mask is zero when the constants are read in digital mode
+        let mut responses = DS2_RESPONSES;
+        responses[5][3..].fill(0x00);
+        let caps = DeviceCapabilities::from(&info(&responses));
+        assert!(!caps.is_pressures());
+        assert!(caps.with_model(DeviceType::DualShock2 as u8).is_pressures());
+        assert!(!caps.with_model(DeviceType::DualShock1 as u8).is_pressures());
+    }
+
+    #[test]
+    fn clone_answers_zeros() {
+        let caps = DeviceCapabilities::from(&DeviceCInfo::default());
+        assert_eq!((0, 0), (caps.actuator_count(), caps.mode_count()));
+        assert!(caps.actuators().is_empty() && !caps.is_pressures());
+    }
+}
@@ -1,12 +1,24 @@
+use embedded_hal::{digital, spi};
+
 pub const DATA_HEADER_SIZE: u8 = 3;
 pub const DATA_PAYLOAD_SIZE: u8 = 18;
 pub const DATA_SIZE: usize = (DATA_HEADER_SIZE + DATA_PAYLOAD_SIZE) as usize;
 pub const TX_PSX: u8 = 0x00;
 pub const TX_PS2: u8 = 0x5A;
+pub const MODE_DIGITAL: u8 = 0x00;
+pub const MODE_ANALOG: u8 = 0x01;
+pub const MODE_UNLOCK: u8 = 0x02;
+pub const MODE_LOCK: u8 = 0x03;
+/// SetupPoll masks of the response bytes: buttons + sticks or all 18 bytes
+pub const POLL_ANALOG: [u8; 3] = [0x3F, 0x00, 0x00];
+pub const POLL_PRESSURES: [u8; 3] = [0xFF, 0xFF, 0x03];
+/// MapMotors payload: poll bytes 3 and 4 drive small and large motors, 0xFF - not mapped
+pub const MOTORS_MAP: [u8; 6] = [0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF];
+pub const MOTORS_UNMAP: [u8; 6] = [0xFF; 6];
 
 /// Enum of device commands
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Command {
     QueryDS2AnalogMode = 0x41,
     MainPoll = 0x42,
@@ -20,9 +32,30 @@ pub enum Command {
     SetupPoll = 0x4F,         // config mode only
 }
 
+impl TryFrom<u8> for Command {
+    type Error = u8;
+    fn try_from(v: u8) -> Result<Self, u8> {
+        match v {
+            0x41 => Ok(Self::QueryDS2AnalogMode),
+            0x42 => Ok(Self::MainPoll),
+            0x43 => Ok(Self::Config),
+            0x44 => Ok(Self::SetModeAndLock),
+            0x45 => Ok(Self::QueryModelAndMode),
+            0x46 => Ok(Self::GetConst1),
+            0x47 => Ok(Self::GetConst2),
+            0x4C => Ok(Self::GetConst3),
+            0x4D => Ok(Self::MapMotors),
+            0x4F => Ok(Self::SetupPoll),
+            _ => Err(v),
+        }
+    }
+}
+
 /// enum mode of ps2 device
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DeviceMode {
     Unknown = 0x00,
     Digital = 0x40,
@@ -43,12 +76,15 @@ impl From<u8> for DeviceMode {
 
 /// enum type of ps2 device
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DeviceType {
     Unknown = 0x00,
     DualShock1 = 0x01,
     DualShock2 = 0x03,
-    //GuitarHero,
+    /// Answers the model of DualShock, detected by the Left button held all the time
+    GuitarHero,
 }
 
 impl From<u8> for DeviceType {
@@ -66,33 +102,147 @@ pub struct DeviceCInfo {
     pub unknown2: [u8; 5],
     pub unknown1: [u8; 10],
     pub unknown3: [u8; 10],
+    /// Response of `QueryDS2AnalogMode` in config mode
+    pub poll_mask: [u8; 5],
+}
+
+/// Commands and indexes reading parts of [`DeviceCInfo`], 5 bytes each
+pub(crate) const DEVICE_INFO_PARTS: [(Command, u8); 6] = [
+    (Command::GetConst1, 0),
+    (Command::GetConst1, 1),
+    (Command::GetConst2, 0),
+    (Command::GetConst3, 0),
+    (Command::GetConst3, 1),
+    (Command::QueryDS2AnalogMode, 0),
+];
+
+impl DeviceCInfo {
+    /// Store part `n` of [`DEVICE_INFO_PARTS`]
+    pub(crate) fn set_part(&mut self, n: usize, data: &[u8]) {
+        let part = match n {
+            0 => &mut self.unknown1[..5],
+            1 => &mut self.unknown1[5..],
+            2 => &mut self.unknown2[..],
+            3 => &mut self.unknown3[..5],
+            4 => &mut self.unknown3[5..],
+            _ => &mut self.poll_mask[..],
+        };
+        part.copy_from_slice(&data[..5]);
+    }
+}
+
+/// Settings applied to device in config mode
+#[derive(Copy, Clone, PartialEq)]
+pub struct DeviceConfig {
+    /// Switch to analog mode and enable the 18 bytes response with button pressures
+    pub pressures: bool,
+    /// Map vibration motors to the poll command
+    pub motors: bool,
+    /// Mode to switch, [`DeviceMode::Unknown`] keeps the current mode
+    pub mode: DeviceMode,
+    /// Lock mode, the Analog button of device is ignored
+    pub lock: bool,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            pressures: false,
+            motors: false,
+            mode: DeviceMode::Unknown,
+            lock: false,
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// SetModeAndLock payload, pressures are reported in analog mode only
+    pub fn tx_mode_and_lock(&self) -> Option<[u8; 2]> {
+        let lock = match self.lock {
+            true => MODE_LOCK,
+            false => MODE_UNLOCK,
+        };
+        match self.mode {
+            _ if self.pressures => Some([MODE_ANALOG, lock]),
+            DeviceMode::Digital => Some([MODE_DIGITAL, lock]),
+            DeviceMode::Analog | DeviceMode::DualShock2Native => Some([MODE_ANALOG, lock]),
+            DeviceMode::Unknown => None,
+        }
+    }
+    /// MapMotors payload
+    pub fn tx_motors(&self) -> [u8; 6] {
+        match self.motors {
+            true => MOTORS_MAP,
+            false => MOTORS_UNMAP,
+        }
+    }
+    /// SetupPoll payload
+    pub fn tx_poll_mask(&self) -> [u8; 3] {
+        match self.pressures {
+            true => POLL_PRESSURES,
+            false => POLL_ANALOG,
+        }
+    }
 }
 
 /// enum state of device connection
 #[repr(u8)]
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum DeviceState {
-    ConnectionError,
+    /// No device answered, probed again after a delay
+    Disconnected,
+    /// Looking for device
+    Probing,
+    /// Device answered, reading its type and applying settings
+    Configuring,
     Connected,
+    /// Device stopped answering several polls in a row
+    Lost,
 }
 
-impl<T> From<CResult<T>> for DeviceState {
-    fn from(r: CResult<T>) -> Self {
+impl<T, E> From<CResult<T, E>> for DeviceState {
+    fn from(r: CResult<T, E>) -> Self {
         match r {
             Ok(_) => Self::Connected,
-            _ => Self::ConnectionError,
+            _ => Self::Disconnected,
         }
     }
 }
 
 /// Result of operation device
-pub type CResult<T> = Result<T, ControllerError>;
+pub type CResult<T, E> = Result<T, ControllerError<E>>;
 
-///
-#[repr(u8)]
-#[derive(Copy, Clone, Debug)]
-pub enum ControllerError {
-    NoResponse = 0,
+/// Errors of device communication, `E` is the error of transport
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ControllerError<E> {
+    NoResponse,
     BadHeader,
     LogicError,
+    /// GPIO or SPI failure
+    Transport(E),
+}
+
+/// Error type of transport or driver
+pub trait ErrorType {
+    type Error: core::fmt::Debug;
+}
+
+impl<T: ErrorType + ?Sized> ErrorType for &mut T {
+    type Error = T::Error;
+}
+
+/// Error of the GPIO and SPI transports
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DeviceError {
+    Pin(digital::ErrorKind),
+    Spi(spi::ErrorKind),
+}
+
+impl DeviceError {
+    pub fn pin<E: digital::Error>(e: E) -> Self {
+        Self::Pin(e.kind())
+    }
+    pub fn spi<E: spi::Error>(e: E) -> Self {
+        Self::Spi(e.kind())
+    }
 }
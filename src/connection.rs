@@ -0,0 +1,166 @@
+use crate::commands::DeviceState;
+use crate::events::Queue;
+use crate::scheduler::is_due;
+
+/// Capacity of the queue of state changes
+pub const STATE_QUEUE_SIZE: usize = 8;
+
+/// Change of the connection state
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StateChange {
+    pub from: DeviceState,
+    pub to: DeviceState,
+    /// Timestamp of the poll in ms
+    pub at_ms: u32,
+}
+
+/// Settings of hot-plug detection
+#[derive(Copy, Clone, PartialEq)]
+pub struct ConnectionConfig {
+    /// Consecutive failed polls before the device is lost, 1 - lost on first failure
+    pub max_failures: u8,
+    /// Delay before the next probe, doubled after every failed probe
+    pub probe_interval_ms: u32,
+    /// Maximum delay between probes
+    pub max_probe_interval_ms: u32,
+}
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            probe_interval_ms: 100,
+            max_probe_interval_ms: 2000,
+        }
+    }
+}
+
+/// Failure counter, probe backoff and queue of state changes
+pub(crate) struct Connection {
+    config: ConnectionConfig,
+    failures: u8,
+    backoff_ms: u32,
+    probe_at_ms: u32,
+    changes: Queue<StateChange, STATE_QUEUE_SIZE>,
+}
+impl Connection {
+    pub fn new() -> Self {
+        let config = ConnectionConfig::default();
+        Self {
+            config,
+            failures: 0,
+            backoff_ms: config.probe_interval_ms,
+            probe_at_ms: 0,
+            changes: Queue::new(),
+        }
+    }
+    pub fn set_config(&mut self, config: ConnectionConfig) {
+        self.config = config;
+        self.backoff_ms = config.probe_interval_ms;
+    }
+    /// Number of consecutive failed polls
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+    pub fn is_probe_due(&self, now_ms: u32) -> bool {
+        is_due(now_ms, self.probe_at_ms)
+    }
+    /// Device is connected, reset failures and backoff
+    pub fn connected(&mut self) {
+        self.failures = 0;
+        self.backoff_ms = self.config.probe_interval_ms;
+    }
+    /// Schedule the next probe with increasing delay
+    pub fn probe_failed(&mut self, now_ms: u32) {
+        self.probe_at_ms = now_ms.wrapping_add(self.backoff_ms);
+        self.backoff_ms = self
+            .backoff_ms
+            .saturating_mul(2)
+            .min(self.config.max_probe_interval_ms);
+    }
+    /// Count failed poll, returns true if the device is lost
+    pub fn poll_failed(&mut self, now_ms: u32) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        let is_lost = self.failures >= self.config.max_failures;
+        if is_lost {
+            // the first probe follows immediately, a pad may be re-inserted
+            self.probe_at_ms = now_ms;
+        }
+        is_lost
+    }
+    pub fn poll_ok(&mut self) {
+        self.failures = 0;
+    }
+    pub fn push(&mut self, change: StateChange) {
+        self.changes.push(change);
+    }
+    pub fn pop(&mut self) -> Option<StateChange> {
+        self.changes.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{create_mock_controller, MockPad};
+
+    #[test]
+    fn backoff_doubles_up_to_maximum() {
+        let mut connection = Connection::new();
+        let mut now = 0;
+        let mut delays = [0u32; 7];
+        for delay in delays.iter_mut() {
+            connection.probe_failed(now);
+            let next = (now..now + 3000)
+                .find(|t| connection.is_probe_due(*t))
+                .unwrap();
+            *delay = next - now;
+            now = next;
+        }
+        assert_eq!([100, 200, 400, 800, 1600, 2000, 2000], delays);
+
+        connection.connected();
+        connection.probe_failed(now);
+        assert!(!connection.is_probe_due(now + 99) && connection.is_probe_due(now + 100));
+    }
+
+    #[test]
+    fn lost_after_failures() {
+        let mut connection = Connection::new();
+        assert!(!connection.poll_failed(0) && !connection.poll_failed(16));
+        connection.poll_ok();
+        assert_eq!(0, connection.failures());
+        assert!(!connection.poll_failed(32) && !connection.poll_failed(48));
+        assert!(connection.poll_failed(64));
+        assert_eq!(3, connection.failures());
+        // the first probe is not delayed
+        assert!(connection.is_probe_due(64));
+
+        connection.set_config(ConnectionConfig {
+            max_failures: 1,
+            ..Default::default()
+        });
+        connection.connected();
+        assert!(connection.poll_failed(80));
+    }
+
+    #[test]
+    fn controller_is_lost() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        while controller.next_state_change().is_some() {}
+
+        pad.set_connected(false);
+        controller.poll();
+        controller.poll();
+        assert_eq!(DeviceState::Connected, controller.state);
+        assert_eq!(None, controller.next_state_change());
+        controller.poll();
+        let change = controller.next_state_change().unwrap();
+        assert_eq!(
+            (DeviceState::Connected, DeviceState::Lost),
+            (change.from, change.to)
+        );
+        assert_eq!(DeviceState::Lost, controller.state);
+    }
+}
@@ -1,16 +1,39 @@
+#[cfg(feature = "async")]
+use crate::asynch::{
+    Async, Driver as AsyncDriver, PsxDriver as AsyncPsxDriver,
+    PsxTrasferData as AsyncPsxTrasferData,
+};
 use crate::{
-    buttons::{AnalogSticks, Button /*GuitarButton*/},
-    commands::{CResult, DeviceCInfo, DeviceMode, DeviceState, DeviceType},
-    device::PsxDevice,
-    driver::{Driver, PsxDriver},
-    Gamepad,
+    buttons::{
+        AnalogSticks, Button, ButtonPressures, ButtonSet, ButtonState, GuitarButton, Strum,
+        GUITAR_FRETS,
+    },
+    capabilities::DeviceCapabilities,
+    commands::{
+        CResult, ControllerError, DeviceCInfo, DeviceConfig, DeviceMode, DeviceState, DeviceType,
+        ErrorType,
+    },
+    connection::{Connection, ConnectionConfig, StateChange},
+    device::{ClockControl, PsxDevice, PsxTrasferData, SpiPsxDevice, TimingProfile},
+    driver::{Driver, Packet, PsxDriver},
+    events::{ButtonEvent, ButtonEvents, EventConfig},
+    fingerprint::{identify_device, DeviceModel, Quirks, Signature},
+    memcard::MemoryCard,
+    recording::{Recorder, RecordingTransport, ReplayTransport},
+    rumble::{Motors, Rumble},
+    scheduler::{Step, StepResult},
+    snapshot::GamepadSnapshot,
+    sticks::{ProcessedSticks, StickProcessor},
+    Gamepad, GuitarGamepad,
 };
 use embedded_hal::{
     delay::DelayNs,
     digital::{InputPin, OutputPin},
+    spi::SpiDevice,
 };
 
 /// Creating an instance [`Controller`] to work with a device connected to ordinary digital pins
+#[allow(clippy::type_complexity)]
 pub fn create_psx_controller<Dat, Cmd, Att, Clk, D>(
     dat: Dat,
     cmd: Cmd,
@@ -25,149 +48,1085 @@ where
     Clk: OutputPin,
     D: DelayNs,
 {
-    let dev = PsxDevice::new(dat, cmd, att, clk, delay);
+    let dev = PsxDevice::new(dat, cmd, att, clk, delay, TimingProfile::default());
+    let driver = PsxDriver::new(dev);
+    Controller::new(driver)
+}
+
+/// Creating an instance [`Controller`] with the ACK pin connected, an absent device
+/// is detected by the missing acknowledge instead of a bad response
+#[allow(clippy::type_complexity)]
+pub fn create_psx_controller_with_ack<Dat, Cmd, Att, Clk, Ack, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    ack: Ack,
+    delay: D,
+) -> Controller<PsxDriver<PsxDevice<Dat, Cmd, Att, Clk, D, Ack>>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    Ack: InputPin,
+    D: DelayNs,
+{
+    let dev = PsxDevice::new(dat, cmd, att, clk, delay, TimingProfile::default()).with_ack(ack);
+    let driver = PsxDriver::new(dev);
+    Controller::new(driver)
+}
+
+/// Creating an instance [`Controller`] to work with a device connected to the SPI peripheral
+pub fn create_spi_controller<Spi, Att, D>(
+    spi: Spi,
+    att: Att,
+    delay: D,
+) -> Controller<PsxDriver<SpiPsxDevice<Spi, Att, D>>>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
+    D: DelayNs,
+{
+    let dev = SpiPsxDevice::new(spi, att, delay);
     let driver = PsxDriver::new(dev);
     Controller::new(driver)
 }
 
 const NO_BUTTONS: u16 = 0xFFFF;
+pub(crate) const POLL_INTERVAL_MS: u32 = 16;
+
+/// Device state read by one polling
+struct PollData {
+    buttons: u16,
+    cmode: DeviceMode,
+    analog: AnalogSticks,
+    pressures: Option<ButtonPressures>,
+}
 
 /// Device management controller
-pub struct Controller<D> {
+pub struct Controller<D: ErrorType> {
     pub ctype: DeviceType,
     pub state: DeviceState,
     pub cmode: DeviceMode,
     pub is_analog_led: bool,
     pub info: DeviceCInfo,
+    pub capabilities: DeviceCapabilities,
+    pub model: DeviceModel,
     analog: AnalogSticks,
-    buttons: u16,
-    buttons_prev: u16,
-    // enable_rumble: bool,
-    // enable_pressures: bool,
-    is_first_connect: bool,
+    buttons: ButtonState,
+    pressures: Option<ButtonPressures>,
+    rumble: Rumble,
+    poll_interval_ms: u32,
+    timestamp_ms: u32,
+    events: ButtonEvents,
+    stick_processor: StickProcessor,
+    is_calibration_pending: bool,
+    is_guitar_detection: bool,
+    config: DeviceConfig,
+    is_config_changed: bool,
+    quirks: Quirks,
+    signature: Signature,
+    connection: Connection,
+    last_error: Option<ControllerError<D::Error>>,
     driver: D,
 }
 
-impl<D: Driver> Controller<D> {
+impl<D: ErrorType> Controller<D> {
     /// Create new instance of ps2 device
     pub fn new(driver: D) -> Self {
         Self {
             ctype: DeviceType::Unknown,
-            state: DeviceState::ConnectionError,
+            state: DeviceState::Disconnected,
             cmode: DeviceMode::Unknown,
             is_analog_led: false,
             info: DeviceCInfo::default(),
+            capabilities: DeviceCapabilities::default(),
+            model: DeviceModel::Unknown,
             analog: AnalogSticks::default(),
-            buttons: NO_BUTTONS,
-            buttons_prev: NO_BUTTONS,
-            is_first_connect: true,
+            buttons: ButtonState::RELEASED,
+            pressures: None,
+            rumble: Rumble::default(),
+            poll_interval_ms: POLL_INTERVAL_MS,
+            timestamp_ms: 0,
+            events: ButtonEvents::new(),
+            stick_processor: StickProcessor::default(),
+            is_calibration_pending: false,
+            is_guitar_detection: true,
+            config: DeviceConfig::default(),
+            is_config_changed: false,
+            quirks: Quirks::default(),
+            signature: Signature::default(),
+            connection: Connection::new(),
+            last_error: None,
             driver,
         }
     }
-    /// Connect to device and detect his type
-    pub fn connect(&mut self) {
-        self.state = self.driver.initialize().into();
-        self.state = match self.state {
-            DeviceState::Connected => match self.driver.query_model_and_mode() {
-                Ok((ctype, is_led)) => {
-                    self.ctype = ctype.into();
-                    self.cmode = self.driver.current_mode();
-                    self.is_analog_led = 0x01 == is_led;
-                    Ok(())
+    /// Replace the driver keeping the state of the device, the error type stays the same
+    pub fn map_driver<T, F>(self, f: F) -> Controller<T>
+    where
+        T: ErrorType<Error = D::Error>,
+        F: FnOnce(D) -> T,
+    {
+        let Self {
+            ctype,
+            state,
+            cmode,
+            is_analog_led,
+            info,
+            capabilities,
+            model,
+            analog,
+            buttons,
+            pressures,
+            rumble,
+            poll_interval_ms,
+            timestamp_ms,
+            events,
+            stick_processor,
+            is_calibration_pending,
+            is_guitar_detection,
+            config,
+            is_config_changed,
+            quirks,
+            signature,
+            connection,
+            last_error,
+            driver,
+        } = self;
+        Controller {
+            ctype,
+            state,
+            cmode,
+            is_analog_led,
+            info,
+            capabilities,
+            model,
+            analog,
+            buttons,
+            pressures,
+            rumble,
+            poll_interval_ms,
+            timestamp_ms,
+            events,
+            stick_processor,
+            is_calibration_pending,
+            is_guitar_detection,
+            config,
+            is_config_changed,
+            quirks,
+            signature,
+            connection,
+            last_error,
+            driver: f(driver),
+        }
+    }
+    /// Apply result of the model and mode query
+    fn update_model(&mut self, (ctype, is_led): (u8, u8), cmode: DeviceMode) {
+        // the response of device without config mode is a poll
+        self.signature.model = ctype;
+        self.signature.is_config = DeviceMode::DualShock2Native == cmode;
+        self.ctype = ctype.into();
+        self.cmode = cmode;
+        self.is_analog_led = 0x01 == is_led;
+    }
+    /// Constants of config mode and capabilities decoded from them
+    fn update_info(&mut self, info: DeviceCInfo) {
+        self.info = info;
+        self.signature.info = info;
+        self.capabilities = self.signature.capabilities();
+    }
+    /// Apply result of the polling, sticks and mode are read only on success
+    fn update_buttons(&mut self, result: CResult<PollData, D::Error>) {
+        match result {
+            Ok(data) => {
+                self.cmode = data.cmode;
+                self.buttons.update(match self.is_guitar() {
+                    true => data.buttons | GuitarButton::LeftStrum as u16,
+                    false => data.buttons,
+                });
+                self.analog = data.analog;
+                self.pressures = data.pressures;
+                self.events.update(self.buttons.raw(), self.timestamp_ms);
+                if self.is_calibration_pending
+                    && DeviceMode::Analog == self.cmode
+                    && !self.is_guitar()
+                {
+                    self.calibrate_sticks();
                 }
-                Err(e) => Err(e),
+                self.connection.poll_ok();
+                self.last_error = None;
             }
-            .into(),
-            _ => self.state,
+            Err(e) => self.poll_failed(e),
+        }
+    }
+    /// Failed operation of connected device, it is lost after several failures in a row.
+    /// Buttons and sticks keep the last values until then
+    fn poll_failed(&mut self, e: ControllerError<D::Error>) {
+        self.last_error = Some(e);
+        if self.connection.poll_failed(self.timestamp_ms) {
+            self.buttons.release();
+            self.analog = AnalogSticks::default();
+            self.pressures = None;
+            self.events.update(NO_BUTTONS, self.timestamp_ms);
+            self.set_state(DeviceState::Lost);
         }
     }
-    /// Simple reconnect
+    /// Result of operation while connecting, failure leaves device disconnected
+    fn track<T>(&mut self, result: CResult<T, D::Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.last_error = Some(e);
+                self.connection.probe_failed(self.timestamp_ms);
+                self.set_state(DeviceState::Disconnected);
+                None
+            }
+        }
+    }
+    /// Device answered the probe, settings are sent again and sticks calibrated
+    fn begin_configure(&mut self, id: u8) {
+        self.signature.id = id;
+        self.set_state(DeviceState::Configuring);
+        self.is_calibration_pending = true;
+        self.request_config();
+    }
+    fn finish_connect(&mut self) {
+        self.connection.connected();
+        self.last_error = None;
+        self.set_state(DeviceState::Connected);
+    }
+    /// Change state of connection and remember the change
+    fn set_state(&mut self, state: DeviceState) {
+        if self.state != state {
+            self.connection.push(StateChange {
+                from: self.state,
+                to: state,
+                at_ms: self.timestamp_ms,
+            });
+            self.state = state;
+        }
+    }
+    /// Next change of connection state, changes are kept until read
+    pub fn next_state_change(&mut self) -> Option<StateChange> {
+        self.connection.pop()
+    }
+    /// Set failures to lose device and delays between probes
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.connection.set_config(config);
+    }
+    /// Copy of the state read by the last poll
+    pub fn snapshot(&self) -> GamepadSnapshot {
+        GamepadSnapshot::new(
+            self.buttons.pressed(),
+            self.buttons.pressed_prev(),
+            self.analog,
+            self.pressures,
+            self.cmode,
+            self.ctype,
+            self.timestamp_ms,
+        )
+    }
+    /// Number of failed polls in a row
+    pub fn failures(&self) -> u8 {
+        self.connection.failures()
+    }
+    /// Move clock to `now_ms`, returns elapsed time
+    pub(crate) fn advance_time(&mut self, now_ms: u32) -> u32 {
+        let elapsed_ms = now_ms.wrapping_sub(self.timestamp_ms);
+        self.timestamp_ms = now_ms;
+        elapsed_ms
+    }
+    /// Advance timed vibration before the next poll
+    pub(crate) fn tick_rumble(&mut self, elapsed_ms: u32) {
+        self.rumble.tick(elapsed_ms);
+    }
+    pub(crate) fn is_config_pending(&self) -> bool {
+        self.is_config_changed && !self.quirks.no_config
+    }
+    pub(crate) fn is_probe_due(&self, now_ms: u32) -> bool {
+        self.connection.is_probe_due(now_ms)
+    }
+    /// Error of the last failed operation, cleared by successful polling or connection.
+    /// [`ControllerError::Transport`] is a failure of pins or SPI, not a missing device
+    pub fn last_error(&self) -> Option<&ControllerError<D::Error>> {
+        self.last_error.as_ref()
+    }
+    /// Enable the full DualShock 2 response with pressures of 12 buttons,
+    /// switches device to analog mode, applied before next polling
+    pub fn enable_pressures(&mut self, enable: bool) {
+        if self.config.pressures != enable {
+            self.config.pressures = enable;
+            self.is_config_changed = true;
+        }
+    }
+    /// Switch to the guitar layout when the model is identified as Guitar Hero,
+    /// on by default. Applied at next connect
+    pub fn enable_guitar_detection(&mut self, enable: bool) {
+        self.is_guitar_detection = enable;
+    }
+    /// Switch device to analog or digital mode, applied before next polling
+    /// and after every reconnect. Locked mode ignores the Analog button of device
+    pub fn set_mode(&mut self, mode: DeviceMode, lock: bool) {
+        self.config.mode = mode;
+        self.config.lock = lock;
+        self.is_config_changed = true;
+    }
+    /// Set steady vibration, small motor is on/off, large motor speed 0 - 255.
+    /// Motors are enabled before next polling, values are sent with every poll
+    pub fn set_rumble(&mut self, small: bool, large: u8) {
+        self.rumble.set(Motors::new(small, large));
+        self.enable_motors();
+    }
+    /// Timed vibration, overrides steady values for `duration_ms`
+    pub fn rumble_pulse(&mut self, small: bool, large: u8, duration_ms: u32) {
+        self.rumble.pulse(Motors::new(small, large), duration_ms);
+        self.enable_motors();
+    }
+    /// Stop motors and cancel timed vibration
+    pub fn stop_rumble(&mut self) {
+        self.rumble.stop();
+    }
+    /// Current values of motors
+    pub fn rumble(&self) -> Motors {
+        self.rumble.current()
+    }
+    /// Interval between calls of poll, used without external clock
+    pub fn set_poll_interval(&mut self, interval_ms: u32) {
+        self.poll_interval_ms = interval_ms;
+    }
+    /// Time of the last poll in ms
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp_ms
+    }
+    /// Next button event, events are kept until read
+    pub fn next_event(&mut self) -> Option<ButtonEvent> {
+        self.events.pop()
+    }
+    /// Number of events in queue
+    pub fn events_len(&self) -> usize {
+        self.events.len()
+    }
+    /// Number of events dropped since queue was full
+    pub fn events_dropped(&self) -> u32 {
+        self.events.dropped()
+    }
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+    /// Set deadzones and response curve, the calibrated center is kept
+    pub fn set_stick_processor(&mut self, processor: StickProcessor) {
+        let center = self.stick_processor.center;
+        self.stick_processor = processor;
+        self.stick_processor.center = center;
+    }
+    pub fn stick_processor(&self) -> &StickProcessor {
+        &self.stick_processor
+    }
+    /// Use current position of sticks as center, sticks must be released.
+    /// Called automatically on first polling in analog mode after (re)connect
+    pub fn calibrate_sticks(&mut self) -> bool {
+        self.is_calibration_pending = false;
+        self.stick_processor.calibrate(self.analog)
+    }
+    /// Set thresholds of hold and auto-repeat events
+    pub fn set_event_config(&mut self, config: EventConfig) {
+        self.events.set_config(config);
+    }
+    fn enable_motors(&mut self) {
+        if !self.config.motors {
+            self.config.motors = true;
+            self.is_config_changed = true;
+        }
+    }
+    /// Settings differ from device defaults must be sent after (re)connect
+    fn request_config(&mut self) {
+        self.is_config_changed |= self.config != DeviceConfig::default();
+    }
+    /// Settings with the quirks of the model applied
+    fn device_config(&self) -> DeviceConfig {
+        DeviceConfig {
+            pressures: self.config.pressures && !self.quirks.no_pressures,
+            ..self.config
+        }
+    }
+    /// Quirks of the identified model applied by the driver
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+    /// Identify the model by the responses collected while connecting
+    fn identify(&mut self) -> Quirks {
+        if !self.signature.is_config {
+            // config commands were answered as polls
+            self.update_info(DeviceCInfo::default());
+        }
+        let fingerprint = identify_device(&self.signature);
+        self.model = fingerprint.model;
+        // Guitar Hero answers the model of DualShock, the layout of buttons differs
+        if self.is_guitar_detection && DeviceModel::GuitarHero == self.model {
+            self.ctype = DeviceType::GuitarHero;
+        }
+        fingerprint.quirks
+    }
+}
+
+impl<D: Driver> Controller<D> {
+    /// Override quirks of the identified model, settings are sent again if they differ
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        if self.quirks != quirks {
+            self.quirks = quirks;
+            self.request_config();
+        }
+        self.driver.set_extra_sleep(quirks.extra_sleep_ns);
+    }
+    /// Connect to device and detect his type, waits for device up to 10 queries
+    pub fn connect(&mut self) {
+        self.set_state(DeviceState::Probing);
+        let result = self.driver.initialize();
+        self.configure(result);
+    }
+    /// Detect type and mode of device again and apply settings
     pub fn reconnect(&mut self) {
-        self.is_first_connect = false;
-        self.state = self.driver.initialize().into();
+        self.connect();
+    }
+    /// Single query without waiting, polling probes disconnected device
+    fn probe(&mut self) {
+        self.set_state(DeviceState::Probing);
+        let result = self.driver.probe();
+        self.configure(result);
+    }
+    /// Apply settings and read type and mode of device answered the probe
+    fn configure(&mut self, probe: CResult<(), D::Error>) {
+        if self.track(probe).is_none() {
+            return;
+        }
+        self.begin_configure(self.driver.current_id());
+        let result = self.apply_config();
+        if self.track(result).is_none() {
+            return;
+        }
+        let result = self.driver.query_model_and_mode();
+        let Some(model) = self.track(result) else {
+            return;
+        };
+        self.update_model(model, self.driver.current_mode());
+        let result = self.driver.read_device_info();
+        let Some(info) = self.track(result) else {
+            return;
+        };
+        self.update_info(info);
+        let quirks = self.identify();
+        self.set_quirks(quirks);
+        self.finish_connect();
     }
-    /// Polling device buttons and sticks
+    fn apply_config(&mut self) -> CResult<(), D::Error> {
+        if !self.is_config_pending() {
+            return Ok(());
+        }
+        let result = self.driver.setup(&self.device_config());
+        self.is_config_changed = result.is_err();
+        result
+    }
+    /// Polling device buttons and sticks, timestamp advances by poll interval
     pub fn poll(&mut self) {
+        self.poll_at(self.timestamp_ms.wrapping_add(self.poll_interval_ms));
+    }
+    /// Polling at time `now_ms` of monotonic clock, it timestamps events and timed vibration.
+    /// Disconnected device is probed when the delay of backoff expires
+    pub fn poll_at(&mut self, now_ms: u32) {
+        let elapsed_ms = self.advance_time(now_ms);
+        self.tick_rumble(elapsed_ms);
         match self.state {
-            DeviceState::Connected => {
-                self.state = self._poll().into();
+            DeviceState::Connected => self._poll(),
+            _ if self.connection.is_probe_due(now_ms) => self.probe(),
+            _ => {}
+        };
+    }
+
+    fn _poll(&mut self) {
+        if let Err(e) = self.apply_config() {
+            self.poll_failed(e);
+            return;
+        }
+        let result = match self.config.motors {
+            true => {
+                let (m1, m2) = self.rumble.next().tx_values();
+                self.driver.poll_vibrate(m1, m2)
+            }
+            false => self.driver.poll(),
+        };
+        let result = result.map(|buttons| PollData {
+            buttons,
+            cmode: self.driver.current_mode(),
+            analog: self.driver.analog_sticks(),
+            pressures: self.driver.button_pressures(),
+        });
+        self.update_buttons(result)
+    }
+}
+
+impl<Dev: PsxTrasferData> Controller<PsxDriver<Dev>> {
+    /// Memory card of the same slot, it shares the transport with the controller
+    pub fn memory_card(&mut self) -> MemoryCard<&mut Dev> {
+        MemoryCard::new(self.driver.device_mut())
+    }
+    /// Pass the traffic of the controller to the recorder, the state of the device is kept
+    pub fn record<R: Recorder>(
+        self,
+        recorder: R,
+    ) -> Controller<PsxDriver<RecordingTransport<Dev, R>>> {
+        self.map_driver(|driver| driver.map_device(|dev| RecordingTransport::new(dev, recorder)))
+    }
+    /// Run one step of [`PollScheduler`], at most one packet without pauses
+    pub(crate) fn run_step(&mut self, step: Step) -> StepResult {
+        let packet = match step {
+            Step::Probe => {
+                self.set_state(DeviceState::Probing);
+                Packet::Probe
+            }
+            Step::EnterConfig => Packet::Config(true),
+            Step::ExitConfig => Packet::Config(false),
+            Step::SetModeAndLock => {
+                let config = self.device_config();
+                match (self.is_config_pending(), config.tx_mode_and_lock()) {
+                    (true, Some(mode)) => Packet::SetModeAndLock(mode),
+                    _ => return StepResult::Skipped,
+                }
             }
-            _ => self.reconnect(),
+            Step::MapMotors if self.is_config_pending() => {
+                Packet::MapMotors(self.device_config().tx_motors())
+            }
+            Step::SetupPoll if self.is_config_pending() => {
+                Packet::SetupPoll(self.device_config().tx_poll_mask())
+            }
+            Step::MapMotors | Step::SetupPoll => return StepResult::Skipped,
+            Step::QueryModel => Packet::QueryModel,
+            Step::DeviceInfo(_) if self.quirks.no_config => return StepResult::Skipped,
+            Step::DeviceInfo(n) => Packet::DeviceInfo(n),
+            Step::Finish => {
+                let quirks = self.identify();
+                self.set_quirks(quirks);
+                self.finish_connect();
+                return StepResult::Skipped;
+            }
+            Step::Poll => match self.config.motors {
+                true => {
+                    let (m1, m2) = self.rumble.next().tx_values();
+                    Packet::PollVibrate(m1, m2)
+                }
+                false => Packet::Poll,
+            },
         };
+        if let Err(e) = self.driver.transfer(packet) {
+            match self.state {
+                DeviceState::Connected => self.poll_failed(e),
+                _ => {
+                    self.track::<()>(Err(e));
+                }
+            }
+            return StepResult::Failed;
+        }
+        match step {
+            Step::Probe => self.begin_configure(self.driver.current_id()),
+            Step::SetupPoll => self.is_config_changed = false,
+            Step::QueryModel => {
+                let buf = self.driver.buffer();
+                let model = (buf.rx_data_model(), buf.rx_data_mode());
+                self.update_model(model, self.driver.current_mode());
+            }
+            Step::DeviceInfo(n) => {
+                let mut info = self.info;
+                info.set_part(n, &self.driver.buffer().data[4..]);
+                self.update_info(info);
+            }
+            Step::Poll => {
+                let data = PollData {
+                    buttons: self.driver.buffer().rx_buttons(),
+                    cmode: self.driver.current_mode(),
+                    analog: self.driver.analog_sticks(),
+                    pressures: self.driver.button_pressures(),
+                };
+                self.update_buttons(Ok(data));
+            }
+            _ => {}
+        }
+        StepResult::Sent
+    }
+}
+
+impl<Dev: PsxTrasferData, R: Recorder> Controller<PsxDriver<RecordingTransport<Dev, R>>> {
+    pub fn recorder(&self) -> &R {
+        self.driver.device().recorder()
+    }
+    pub fn recorder_mut(&mut self) -> &mut R {
+        self.driver.device_mut().recorder_mut()
+    }
+}
+
+impl<'a> Controller<PsxDriver<ReplayTransport<'a>>> {
+    /// Transport playing the recording, see [`ReplayTransport::mismatches`]
+    pub fn replay(&self) -> &ReplayTransport<'a> {
+        self.driver.device()
+    }
+}
+
+impl<Dev: PsxTrasferData + ClockControl> Controller<PsxDriver<Dev>> {
+    pub fn timing(&self) -> TimingProfile {
+        self.driver.device().timing()
+    }
+    pub fn set_timing(&mut self, timing: TimingProfile) {
+        self.driver.device_mut().set_timing(timing);
+    }
+    /// Step the clock down from [`TimingProfile::FAST`] until `attempts` probes in a row
+    /// get a valid header. Returns the selected profile, the clock is not changed if none is stable
+    pub fn calibrate_timing(&mut self, attempts: u8) -> Option<TimingProfile> {
+        let current = self.timing();
+        let mut timing = TimingProfile::FAST;
+        loop {
+            self.set_timing(timing);
+            let mut passed = 0;
+            while passed < attempts && self.driver.probe().is_ok() {
+                passed += 1;
+            }
+            if passed == attempts {
+                return Some(timing);
+            }
+            match timing.slower() {
+                Some(slower) => timing = slower,
+                None => break,
+            }
+        }
+        self.set_timing(current);
+        None
     }
+}
 
-    fn _poll(&mut self) -> CResult<()> {
-        match self.driver.poll() {
-            Ok(buttons) => {
-                self.cmode = self.driver.current_mode();
-                self.buttons_prev = self.buttons;
-                self.buttons = buttons;
-                self.analog = self.driver.analog_sticks();
-                Ok(())
+#[cfg(feature = "async")]
+impl<Dev: AsyncPsxTrasferData + ClockControl> Controller<Async<AsyncPsxDriver<Dev>>> {
+    pub fn timing(&self) -> TimingProfile {
+        self.driver.0.device().timing()
+    }
+    pub fn set_timing(&mut self, timing: TimingProfile) {
+        self.driver.0.device_mut().set_timing(timing);
+    }
+    /// Step the clock down from [`TimingProfile::FAST`] until `attempts` probes in a row
+    /// get a valid header. Returns the selected profile, the clock is not changed if none is stable
+    pub async fn calibrate_timing(&mut self, attempts: u8) -> Option<TimingProfile> {
+        let current = self.timing();
+        let mut timing = TimingProfile::FAST;
+        loop {
+            self.set_timing(timing);
+            let mut passed = 0;
+            while passed < attempts && self.driver.0.probe().await.is_ok() {
+                passed += 1;
             }
-            Err(e) => {
-                self.buttons_prev = NO_BUTTONS;
-                self.buttons = NO_BUTTONS;
-                self.analog = AnalogSticks::default();
-                Err(e)
+            if passed == attempts {
+                return Some(timing);
+            }
+            match timing.slower() {
+                Some(slower) => timing = slower,
+                None => break,
             }
         }
+        self.set_timing(current);
+        None
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: AsyncDriver> Controller<Async<D>> {
+    /// Override quirks of the identified model, settings are sent again if they differ
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        if self.quirks != quirks {
+            self.quirks = quirks;
+            self.request_config();
+        }
+        self.driver.0.set_extra_sleep(quirks.extra_sleep_ns);
+    }
+    /// Connect to device and detect his type, waits for device up to 10 queries
+    pub async fn connect(&mut self) {
+        self.set_state(DeviceState::Probing);
+        let result = self.driver.0.initialize().await;
+        self.configure(result).await;
+    }
+    /// Detect type and mode of device again and apply settings
+    pub async fn reconnect(&mut self) {
+        self.connect().await;
+    }
+    /// Single query without waiting, polling probes disconnected device
+    async fn probe(&mut self) {
+        self.set_state(DeviceState::Probing);
+        let result = self.driver.0.probe().await;
+        self.configure(result).await;
+    }
+    /// Apply settings and read type and mode of device answered the probe
+    async fn configure(&mut self, probe: CResult<(), D::Error>) {
+        if self.track(probe).is_none() {
+            return;
+        }
+        self.begin_configure(self.driver.0.current_id());
+        let result = self.apply_config().await;
+        if self.track(result).is_none() {
+            return;
+        }
+        let result = self.driver.0.query_model_and_mode().await;
+        let Some(model) = self.track(result) else {
+            return;
+        };
+        self.update_model(model, self.driver.0.current_mode());
+        let result = self.driver.0.read_device_info().await;
+        let Some(info) = self.track(result) else {
+            return;
+        };
+        self.update_info(info);
+        let quirks = self.identify();
+        self.set_quirks(quirks);
+        self.finish_connect();
+    }
+    async fn apply_config(&mut self) -> CResult<(), D::Error> {
+        if !self.is_config_pending() {
+            return Ok(());
+        }
+        let result = self.driver.0.setup(&self.device_config()).await;
+        self.is_config_changed = result.is_err();
+        result
+    }
+    /// Polling device buttons and sticks, timestamp advances by poll interval
+    pub async fn poll(&mut self) {
+        self.poll_at(self.timestamp_ms.wrapping_add(self.poll_interval_ms))
+            .await;
+    }
+    /// Polling at time `now_ms` of monotonic clock, it timestamps events and timed vibration.
+    /// Disconnected device is probed when the delay of backoff expires
+    pub async fn poll_at(&mut self, now_ms: u32) {
+        let elapsed_ms = self.advance_time(now_ms);
+        self.tick_rumble(elapsed_ms);
+        match self.state {
+            DeviceState::Connected => self._poll().await,
+            _ if self.connection.is_probe_due(now_ms) => self.probe().await,
+            _ => {}
+        };
+    }
+
+    async fn _poll(&mut self) {
+        if let Err(e) = self.apply_config().await {
+            self.poll_failed(e);
+            return;
+        }
+        let result = match self.config.motors {
+            true => {
+                let (m1, m2) = self.rumble.next().tx_values();
+                self.driver.0.poll_vibrate(m1, m2).await
+            }
+            false => self.driver.0.poll().await,
+        };
+        let result = result.map(|buttons| PollData {
+            buttons,
+            cmode: self.driver.0.current_mode(),
+            analog: self.driver.0.analog_sticks(),
+            pressures: self.driver.0.button_pressures(),
+        });
+        self.update_buttons(result)
     }
 }
 
 // implementation Gamepad trait for Device
-impl<T> Gamepad for Controller<T> {
+impl<T: ErrorType> Gamepad for Controller<T> {
     fn is_analog(&self) -> bool {
-        match self.cmode {
-            DeviceMode::Analog => true,
-            _ => false,
-        }
+        matches!(self.cmode, DeviceMode::Analog)
     }
     /// Any button is pressed
     fn is_active(&self) -> bool {
-        self.buttons != NO_BUTTONS
+        self.buttons.is_active()
     }
     /// Check button change self state
     /// True if button state changed from up to down or down to up
     fn is_changed(&self, btn: Button) -> bool {
-        ((self.buttons_prev ^ self.buttons) & btn as u16) > 0
+        self.buttons.is_changed(btn as u16)
     }
     /// Check button is pressed
     /// True if button state is down
     fn is_pressed(&self, btn: Button) -> bool {
-        (!self.buttons & btn as u16) > 0
+        self.buttons.is_pressed(btn as u16)
     }
-    /// Check buttons by mask is pressed
-    /// True if all button state is down
-    fn is_pressed_all(&self, mask: u16) -> bool {
-        (!self.buttons & mask) == mask
+    /// True if all buttons are down
+    fn is_pressed_all(&self, buttons: ButtonSet) -> bool {
+        self.buttons.pressed().all(buttons)
     }
-    /// Check buttons by mask is pressed
-    /// True if any button state is down
-    fn is_pressed_any(&self, mask: u16) -> bool {
-        (!self.buttons & mask) > 0
+    /// True if any button is down
+    fn is_pressed_any(&self, buttons: ButtonSet) -> bool {
+        self.buttons.pressed().any(buttons)
     }
     /// Check button is down
     /// True if button state changed from up to down
     fn is_down(&self, btn: Button) -> bool {
-        self.is_changed(btn) && self.is_pressed(btn)
+        self.buttons.is_down(btn as u16)
     }
     /// Check button is released
     /// True if button state changed from down to up
     fn is_up(&self, btn: Button) -> bool {
-        self.is_changed(btn) && (!self.buttons_prev & btn as u16) > 0
+        self.buttons.is_up(btn as u16)
     }
     /// List all pressed buttons
     /// Return buttons only state down
-    fn pressed_buttons(&self, filter: u16) -> u16 {
-        !self.buttons & filter
+    fn pressed_buttons(&self, filter: ButtonSet) -> ButtonSet {
+        self.buttons.pressed() & filter
     }
     /// Analog stricks values
     fn analog_sticks(&self) -> AnalogSticks {
         self.analog
     }
-    /// Analog value for Guitar Herro device
+    /// Sticks after calibration, deadzones and response curve.
+    /// Zero in digital mode
+    fn processed_sticks(&self) -> ProcessedSticks {
+        match self.is_analog() && !self.is_guitar() {
+            true => self.stick_processor.process(self.analog),
+            false => ProcessedSticks::default(),
+        }
+    }
+    /// Pressure of button (0 - 255)
+    /// Without pressure data 255 for pressed button
+    fn pressure(&self, btn: Button) -> u8 {
+        self.button_pressures().get(btn)
+    }
+    /// Pressures of all 12 pressure sensitive buttons
+    fn button_pressures(&self) -> ButtonPressures {
+        match self.pressures {
+            Some(pressures) => pressures,
+            None => ButtonPressures::from_buttons(self.buttons.pressed()),
+        }
+    }
+    /// Analog value for Guitar Herro device, see [`GuitarGamepad::whammy`]
     fn whammy_bar(&self) -> u8 {
-        self.analog.ly
+        self.whammy()
+    }
+}
+
+impl<T: ErrorType> GuitarGamepad for Controller<T> {
+    fn is_guitar(&self) -> bool {
+        matches!(self.ctype, DeviceType::GuitarHero)
+    }
+    /// Check guitar button is pressed
+    fn is_guitar_pressed(&self, btn: GuitarButton) -> bool {
+        self.buttons.is_pressed(btn as u16)
+    }
+    /// Check guitar button changed state from up to down
+    fn is_guitar_down(&self, btn: GuitarButton) -> bool {
+        self.buttons.is_down(btn as u16)
+    }
+    /// Pressed frets, bit 0 is green, bit 4 is orange
+    fn frets(&self) -> u8 {
+        GUITAR_FRETS
+            .iter()
+            .enumerate()
+            .filter(|(_, fret)| self.is_guitar_pressed(**fret))
+            .fold(0, |acc, (i, _)| acc | (1 << i))
+    }
+    fn strum(&self) -> Strum {
+        match (
+            self.is_guitar_pressed(GuitarButton::UpStrum),
+            self.is_guitar_pressed(GuitarButton::DownStrum),
+        ) {
+            (true, false) => Strum::Up,
+            (false, true) => Strum::Down,
+            _ => Strum::None,
+        }
+    }
+    /// Star power is activated by button or tilt of guitar
+    fn is_star_power(&self) -> bool {
+        self.is_guitar_pressed(GuitarButton::StarPower)
+    }
+    /// Whammy bar in analog mode, 0 for other devices
+    fn whammy(&self) -> u8 {
+        match self.is_guitar() {
+            true => self.analog.ly,
+            false => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::DeviceBuffer;
+    use crate::mock::{create_mock_controller, MockModel, MockPad};
+
+    #[test]
+    fn digital_buttons() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        assert_eq!(DeviceState::Connected, controller.state);
+        assert_eq!(DeviceType::DualShock2, controller.ctype);
+
+        pad.press(Button::Cross);
+        controller.poll();
+        assert_eq!(DeviceMode::Digital, controller.cmode);
+        assert!(controller.is_down(Button::Cross));
+        controller.poll();
+        assert!(controller.is_pressed(Button::Cross) && !controller.is_down(Button::Cross));
+        pad.release(Button::Cross);
+        controller.poll();
+        assert!(controller.is_up(Button::Cross));
+        assert!(!controller.is_active());
+        assert_eq!(AnalogSticks::default(), controller.analog_sticks());
+    }
+
+    #[test]
+    fn analog_mode() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.set_mode(DeviceMode::Analog, true);
+        controller.connect();
+        assert!(pad.is_analog() && pad.is_locked());
+
+        let sticks = AnalogSticks::new(0x10, 0x20, 0x30, 0x40);
+        pad.set_sticks(sticks);
+        pad.press_analog_button();
+        controller.poll();
+        assert_eq!(DeviceMode::Analog, controller.cmode);
+        assert!(controller.is_analog());
+        assert_eq!(sticks, controller.analog_sticks());
+    }
+
+    #[test]
+    fn config_mode() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        assert!(!pad.is_config());
+        assert_eq!(DeviceModel::Scph10010, controller.model);
+        assert_eq!(2, controller.capabilities.actuator_count());
+        assert_eq!(2, controller.capabilities.mode_count());
+
+        // settings are sent in config mode before the next poll
+        let packets = pad.packets();
+        controller.set_mode(DeviceMode::Analog, false);
+        controller.poll();
+        assert!(!pad.is_config());
+        assert!(pad.is_analog() && !pad.is_locked());
+        assert!(pad.packets() - packets > 1);
+        let packets = pad.packets();
+        controller.poll();
+        assert_eq!(1, pad.packets() - packets);
+    }
+
+    #[test]
+    fn pressures() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.enable_pressures(true);
+        controller.connect();
+        assert!(pad.is_pressures());
+
+        pad.set_pressure(Button::Cross, 0x80);
+        controller.poll();
+        assert!(controller.is_pressed(Button::Cross));
+        assert_eq!(0x80, controller.pressure(Button::Cross));
+        assert_eq!(0x00, controller.pressure(Button::Square));
+
+        // DualShock has no pressures, the request is dropped by its quirks
+        let pad = MockPad::new(MockModel::DualShock);
+        let mut controller = create_mock_controller(&pad);
+        controller.enable_pressures(true);
+        controller.connect();
+        controller.poll();
+        assert!(controller.quirks().no_pressures);
+        assert!(!pad.is_pressures());
+    }
+
+    #[test]
+    fn rumble() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        // the large motor is scaled into 0x40 - 0xFF, it does not start below
+        controller.set_rumble(true, 0x80);
+        controller.poll();
+        assert_eq!((0xFF, DeviceBuffer::tx_normolize_motor(0x80)), pad.motors());
+        controller.stop_rumble();
+        controller.poll();
+        assert_eq!((0x00, 0x00), pad.motors());
+
+        // the pulse is counted from the poll which sends it, not from the previous one
+        controller.poll_at(1000);
+        controller.rumble_pulse(true, 0xFF, 30);
+        controller.poll_at(1100);
+        assert_eq!((0xFF, 0xFF), pad.motors());
+        controller.poll_at(1116);
+        assert_eq!((0xFF, 0xFF), pad.motors());
+        controller.poll_at(1132);
+        assert_eq!((0x00, 0x00), pad.motors());
+    }
+
+    #[test]
+    fn guitar_hero() {
+        let pad = MockPad::guitar();
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        assert_eq!(DeviceModel::GuitarHero, controller.model);
+        assert!(controller.is_guitar());
+        // Left is held by the guitar, it is not reported
+        controller.poll();
+        assert!(!controller.is_active());
+        pad.press(Button::RTrigger);
+        pad.press(Button::Cross);
+        pad.press(Button::Up);
+        controller.poll();
+        assert_eq!(0b01001, controller.frets());
+        assert_eq!(Strum::Up, controller.strum());
+        assert!(controller.is_guitar_down(GuitarButton::GreenFret));
+
+        let mut controller = create_mock_controller(&pad);
+        controller.enable_guitar_detection(false);
+        controller.connect();
+        assert_eq!(DeviceModel::GuitarHero, controller.model);
+        assert!(!controller.is_guitar());
+
+        // a DualShock with Left pressed is not a guitar
+        let pad = MockPad::dualshock();
+        pad.press(Button::Left);
+        let mut controller = create_mock_controller(&pad);
+        controller.connect();
+        assert_eq!(DeviceModel::Scph1200, controller.model);
+        assert!(!controller.is_guitar());
+    }
+
+    #[test]
+    fn hot_plug() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad);
+        controller.set_mode(DeviceMode::Analog, true);
+        controller.connect();
+        pad.press(Button::Circle);
+        controller.poll();
+        while controller.next_state_change().is_some() {}
+
+        // the pad is lost after several failed polls, buttons are kept until then
+        pad.set_connected(false);
+        controller.poll();
+        controller.poll();
+        assert_eq!(DeviceState::Connected, controller.state);
+        assert_eq!(2, controller.failures());
+        assert!(controller.is_pressed(Button::Circle));
+        controller.poll();
+        assert_eq!(DeviceState::Lost, controller.state);
+        assert!(!controller.is_active());
+        controller.poll();
+        assert_eq!(DeviceState::Disconnected, controller.state);
+        assert_eq!(Some(&ControllerError::NoResponse), controller.last_error());
+
+        // settings lost with power are sent again
+        pad.set_connected(true);
+        for _ in 0..10 {
+            controller.poll();
+        }
+        assert_eq!(DeviceState::Connected, controller.state);
+        assert!(pad.is_analog() && pad.is_locked());
+        let mut last = None;
+        while let Some(change) = controller.next_state_change() {
+            last = Some(change.to);
+        }
+        assert_eq!(Some(DeviceState::Connected), last);
     }
 }
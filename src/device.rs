@@ -1,31 +1,130 @@
+use crate::commands::{DeviceError, ErrorType};
+use core::convert::Infallible;
 use embedded_hal::{
     delay::DelayNs,
-    digital::{InputPin, OutputPin},
-    //spi::{ErrorType, Operation as SpiOperation, SpiDevice},
+    digital::{self, InputPin, OutputPin},
+    spi::SpiDevice,
 };
+#[cfg(feature = "async")]
+use embedded_hal_async::{delay::DelayNs as AsyncDelayNs, spi::SpiDevice as AsyncSpiDevice};
 
-const CTRL_CLK: u32 = 2000; // ns
-const CTRL_8BIT: u32 = 4000; // ns
 const DRIVER_SLEEP: u32 = 8_000_000; // ns
+/// Pause after a byte on SPI, the clock is set by the bus
+const SPI_BYTE_GAP: u32 = TimingProfile::STANDARD.byte_gap_ns;
+/// Console waits for ACK about 100 us
+const ACK_TIMEOUT: u32 = 100_000; // ns
+/// Step of ACK sampling, the pulse is about 2 us
+const ACK_POLL: u32 = 500; // ns
+
+/// Clock of the bit-bang transport
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimingProfile {
+    /// Half period of CLK
+    pub half_cycle_ns: u32,
+    /// Pause after every byte and after ATT changes
+    pub byte_gap_ns: u32,
+    /// Wait for ACK after a byte, used instead of `byte_gap_ns` when ACK is connected
+    pub ack_timeout_ns: u32,
+}
+impl TimingProfile {
+    /// 125 kHz, for third-party wireless receivers
+    pub const CONSERVATIVE: Self = Self::from_clock_hz(125_000);
+    /// 250 kHz, the clock of the console
+    pub const STANDARD: Self = Self::from_clock_hz(250_000);
+    /// 500 kHz, tolerated by genuine pads
+    pub const FAST: Self = Self::from_clock_hz(500_000);
+    /// Slowest clock tried by calibration
+    pub const MIN_CLOCK_HZ: u32 = 62_500;
+
+    pub const fn from_clock_hz(clock_hz: u32) -> Self {
+        let half_cycle_ns = 500_000_000 / if clock_hz > 0 { clock_hz } else { 1 };
+        Self {
+            half_cycle_ns,
+            byte_gap_ns: half_cycle_ns << 1,
+            ack_timeout_ns: ACK_TIMEOUT,
+        }
+    }
+    pub const fn clock_hz(&self) -> u32 {
+        500_000_000
+            / if self.half_cycle_ns > 0 {
+                self.half_cycle_ns
+            } else {
+                1
+            }
+    }
+    /// Half the clock, `None` below [`Self::MIN_CLOCK_HZ`]
+    pub fn slower(&self) -> Option<Self> {
+        let timing = Self {
+            half_cycle_ns: self.half_cycle_ns << 1,
+            byte_gap_ns: self.byte_gap_ns << 1,
+            ..*self
+        };
+        (timing.clock_hz() >= Self::MIN_CLOCK_HZ).then_some(timing)
+    }
+}
+impl Default for TimingProfile {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// Transport with adjustable clock
+pub trait ClockControl {
+    fn timing(&self) -> TimingProfile;
+    fn set_timing(&mut self, timing: TimingProfile);
+}
+
+impl<T: ClockControl + ?Sized> ClockControl for &mut T {
+    fn timing(&self) -> TimingProfile {
+        T::timing(self)
+    }
+    fn set_timing(&mut self, timing: TimingProfile) {
+        T::set_timing(self, timing)
+    }
+}
+
+/// Placeholder of the ACK pin when it is not connected
+pub struct NoAck;
+
+impl digital::ErrorType for NoAck {
+    type Error = Infallible;
+}
+
+impl InputPin for NoAck {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+}
 
 /// Connector
-struct Connector<Dat, Cmd, Att, Clk> {
+struct Connector<Dat, Cmd, Att, Clk, Ack> {
     dat: Dat,
     cmd: Cmd,
     att: Att,
     clk: Clk,
+    ack: Option<Ack>,
 }
-impl<Dat, Cmd, Att, Clk> Connector<Dat, Cmd, Att, Clk> {
-    pub fn new(dat: Dat, cmd: Cmd, att: Att, clk: Clk) -> Self {
-        Self { dat, cmd, att, clk }
+impl<Dat, Cmd, Att, Clk, Ack> Connector<Dat, Cmd, Att, Clk, Ack> {
+    pub fn new(dat: Dat, cmd: Cmd, att: Att, clk: Clk, ack: Option<Ack>) -> Self {
+        Self {
+            dat,
+            cmd,
+            att,
+            clk,
+            ack,
+        }
     }
 }
 
 /// PsxDevice - connecting the device to the digital pins
-pub struct PsxDevice<Dat, Cmd, Att, Clk, D> {
-    wires: Connector<Dat, Cmd, Att, Clk>,
+pub struct PsxDevice<Dat, Cmd, Att, Clk, D, Ack = NoAck> {
+    wires: Connector<Dat, Cmd, Att, Clk, Ack>,
     delay: D,
-    //half_cycle_ns: u32,
+    timing: TimingProfile,
+    last_ack: Option<bool>,
 }
 impl<Dat, Cmd, Att, Clk, D> PsxDevice<Dat, Cmd, Att, Clk, D>
 where
@@ -33,32 +132,104 @@ where
     Cmd: OutputPin,
     Att: OutputPin,
     Clk: OutputPin,
-    D: DelayNs,
 {
-    pub fn new(dat: Dat, cmd: Cmd, att: Att, clk: Clk, delay: D) -> Self {
-        let wires = Connector::new(dat, cmd, att, clk);
+    pub fn new(dat: Dat, cmd: Cmd, att: Att, clk: Clk, delay: D, timing: TimingProfile) -> Self {
+        let wires = Connector::new(dat, cmd, att, clk, None);
         Self {
             wires,
             delay,
-            //half_cycle_ns: 2000,
+            timing,
+            last_ack: None,
+        }
+    }
+    /// Connect ACK pin, the transport waits for the acknowledge after every byte
+    pub fn with_ack<Ack: InputPin>(self, ack: Ack) -> PsxDevice<Dat, Cmd, Att, Clk, D, Ack> {
+        let Connector {
+            dat, cmd, att, clk, ..
+        } = self.wires;
+        PsxDevice {
+            wires: Connector::new(dat, cmd, att, clk, Some(ack)),
+            delay: self.delay,
+            timing: self.timing,
+            last_ack: None,
         }
     }
 }
 
+impl<Dat, Cmd, Att, Clk, D, Ack> PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
+where
+    Ack: InputPin,
+    D: DelayNs,
+{
+    /// Wait for ACK pulse after a byte, `None` without ACK pin
+    fn wait_ack(&mut self) -> Result<Option<bool>, DeviceError> {
+        let Some(ack) = self.wires.ack.as_mut() else {
+            self.delay.delay_ns(self.timing.byte_gap_ns);
+            return Ok(None);
+        };
+        let mut waited_ns = 0;
+        while ack.is_high().map_err(DeviceError::pin)? {
+            if waited_ns >= self.timing.ack_timeout_ns {
+                // no acknowledge, the device has finished the packet
+                return Ok(Some(false));
+            }
+            self.delay.delay_ns(ACK_POLL);
+            waited_ns += ACK_POLL;
+        }
+        while ack.is_low().map_err(DeviceError::pin)? && waited_ns < self.timing.ack_timeout_ns {
+            self.delay.delay_ns(ACK_POLL);
+            waited_ns += ACK_POLL;
+        }
+        Ok(Some(true))
+    }
+}
+
+impl<Dat, Cmd, Att, Clk, D, Ack> ClockControl for PsxDevice<Dat, Cmd, Att, Clk, D, Ack> {
+    fn timing(&self) -> TimingProfile {
+        self.timing
+    }
+    fn set_timing(&mut self, timing: TimingProfile) {
+        self.timing = timing;
+    }
+}
+
 /// Send commands and recieve data.
-/// Full-duplex protocol operating at 250 kHz
-pub trait PsxTrasferData: DelayNs {
+/// Full-duplex protocol operating at 250 kHz by default
+pub trait PsxTrasferData: DelayNs + ErrorType {
     const SLEEP_NS: u32;
 
-    fn start_cmd(&mut self);
-    fn stop_cmd(&mut self);
-    fn send_8bit(&mut self, byte: u8) -> u8;
+    fn start_cmd(&mut self) -> Result<(), Self::Error>;
+    fn stop_cmd(&mut self) -> Result<(), Self::Error>;
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, Self::Error>;
     fn sleep(&mut self) {
         self.delay_ns(Self::SLEEP_NS);
     }
+    /// Acknowledge of the last byte, `Some(false)` - the device has finished the packet
+    /// or it is absent, `None` - the transport has no ACK line
+    fn last_ack(&self) -> Option<bool> {
+        None
+    }
+}
+
+/// Borrowed transport, the controller and the memory card of one slot share it
+impl<T: PsxTrasferData + ?Sized> PsxTrasferData for &mut T {
+    const SLEEP_NS: u32 = T::SLEEP_NS;
+
+    fn start_cmd(&mut self) -> Result<(), Self::Error> {
+        T::start_cmd(self)
+    }
+    fn stop_cmd(&mut self) -> Result<(), Self::Error> {
+        T::stop_cmd(self)
+    }
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, Self::Error> {
+        T::send_8bit(self, byte)
+    }
+    fn last_ack(&self) -> Option<bool> {
+        T::last_ack(self)
+    }
 }
 
-impl<Dat, Cmd, Att, Clk, D> DelayNs for PsxDevice<Dat, Cmd, Att, Clk, D>
+impl<Dat, Cmd, Att, Clk, D, Ack> DelayNs for PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
 where
     D: DelayNs,
 {
@@ -67,52 +238,263 @@ where
     }
 }
 
-impl<Dat, Cmd, Att, Clk, D> PsxTrasferData for PsxDevice<Dat, Cmd, Att, Clk, D>
+impl<Dat, Cmd, Att, Clk, D, Ack> ErrorType for PsxDevice<Dat, Cmd, Att, Clk, D, Ack> {
+    type Error = DeviceError;
+}
+
+impl<Dat, Cmd, Att, Clk, D, Ack> PsxTrasferData for PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
 where
     Dat: InputPin,
     Cmd: OutputPin,
     Att: OutputPin,
     Clk: OutputPin,
+    Ack: InputPin,
+    D: DelayNs,
+{
+    const SLEEP_NS: u32 = DRIVER_SLEEP;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.wires.cmd.set_high().map_err(DeviceError::pin)?;
+        self.wires.clk.set_high().map_err(DeviceError::pin)?;
+        self.delay_ns(self.timing.half_cycle_ns);
+
+        self.wires.att.set_low().map_err(DeviceError::pin)?; // low enable device
+        self.delay_ns(self.timing.byte_gap_ns);
+        Ok(())
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        self.wires.att.set_high().map_err(DeviceError::pin)?; //high disable device
+        self.delay_ns(self.timing.byte_gap_ns);
+        Ok(())
+    }
+
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        let mut answer: u8 = 0;
+        for i in 0..8 {
+            let bit: u8 = 1 << i;
+            let val: bool = (byte & bit) > 0;
+            self.wires
+                .cmd
+                .set_state(val.into())
+                .map_err(DeviceError::pin)?;
+            self.wires.clk.set_low().map_err(DeviceError::pin)?;
+            self.delay_ns(self.timing.half_cycle_ns);
+            if self.wires.dat.is_high().map_err(DeviceError::pin)? {
+                answer |= bit;
+            }
+            self.wires.clk.set_high().map_err(DeviceError::pin)?;
+            self.delay_ns(self.timing.half_cycle_ns);
+        }
+        self.wires.cmd.set_high().map_err(DeviceError::pin)?;
+        self.last_ack = self.wait_ack()?;
+        Ok(answer)
+    }
+
+    fn last_ack(&self) -> Option<bool> {
+        self.last_ack
+    }
+}
+
+/// SpiPsxDevice - connecting the device to the hardware SPI peripheral
+///
+/// The bus must be configured in SPI mode 3 (CPOL = 1, CPHA = 1), MSB first,
+/// at 250 kHz or less. The PSX protocol is LSB first, so bits are reversed
+/// in software before and after every transfer.
+/// ATT is driven manually because it must stay low for a whole packet,
+/// use a [`SpiDevice`] without its own chip select for the controller.
+pub struct SpiPsxDevice<Spi, Att, D> {
+    spi: Spi,
+    att: Att,
+    delay: D,
+}
+impl<Spi, Att, D> SpiPsxDevice<Spi, Att, D>
+where
+    Att: OutputPin,
+{
+    pub fn new(spi: Spi, att: Att, delay: D) -> Self {
+        Self { spi, att, delay }
+    }
+}
+
+impl<Spi, Att, D> DelayNs for SpiPsxDevice<Spi, Att, D>
+where
+    D: DelayNs,
+{
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay.delay_ns(ns)
+    }
+}
+
+impl<Spi, Att, D> ErrorType for SpiPsxDevice<Spi, Att, D> {
+    type Error = DeviceError;
+}
+
+impl<Spi, Att, D> PsxTrasferData for SpiPsxDevice<Spi, Att, D>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
     Self: DelayNs,
 {
     const SLEEP_NS: u32 = DRIVER_SLEEP;
 
-    fn start_cmd(&mut self) {
-        self.wires.cmd.set_high().unwrap_or(());
-        self.wires.clk.set_high().unwrap_or(());
-        //self.delay.delay_ns(self.half_cycle_ns);
-        self.delay_ns(CTRL_CLK);
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.att.set_low().map_err(DeviceError::pin)?; // low enable device
+        self.delay_ns(SPI_BYTE_GAP);
+        Ok(())
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        self.att.set_high().map_err(DeviceError::pin)?; //high disable device
+        self.delay_ns(SPI_BYTE_GAP);
+        Ok(())
+    }
 
-        self.wires.att.set_low().unwrap_or(()); // low enable device
-                                                //self.delay.delay_ns(self.half_cycle_ns << 1);
-        self.delay_ns(CTRL_8BIT)
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        let mut buf = [byte.reverse_bits()];
+        self.spi
+            .transfer_in_place(&mut buf)
+            .map_err(DeviceError::spi)?;
+        let answer = buf[0].reverse_bits();
+        self.delay_ns(SPI_BYTE_GAP);
+        Ok(answer)
     }
+}
 
-    fn stop_cmd(&mut self) {
-        self.wires.att.set_high().unwrap_or(()); //high disable device
-                                                 // self.delay.delay_ns(self.half_cycle_ns << 1);
-        self.delay_ns(CTRL_8BIT)
+#[cfg(feature = "async")]
+impl<Dat, Cmd, Att, Clk, D, Ack> AsyncDelayNs for PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
+where
+    D: AsyncDelayNs,
+{
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay.delay_ns(ns).await
     }
+}
 
-    fn send_8bit(&mut self, byte: u8) -> u8 {
+#[cfg(feature = "async")]
+impl<Dat, Cmd, Att, Clk, D, Ack> PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
+where
+    Ack: InputPin,
+    D: AsyncDelayNs,
+{
+    /// Wait for ACK pulse after a byte, `None` without ACK pin
+    async fn wait_ack_async(&mut self) -> Result<Option<bool>, DeviceError> {
+        let Some(ack) = self.wires.ack.as_mut() else {
+            self.delay.delay_ns(self.timing.byte_gap_ns).await;
+            return Ok(None);
+        };
+        let mut waited_ns = 0;
+        while ack.is_high().map_err(DeviceError::pin)? {
+            if waited_ns >= self.timing.ack_timeout_ns {
+                // no acknowledge, the device has finished the packet
+                return Ok(Some(false));
+            }
+            self.delay.delay_ns(ACK_POLL).await;
+            waited_ns += ACK_POLL;
+        }
+        while ack.is_low().map_err(DeviceError::pin)? && waited_ns < self.timing.ack_timeout_ns {
+            self.delay.delay_ns(ACK_POLL).await;
+            waited_ns += ACK_POLL;
+        }
+        Ok(Some(true))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<Dat, Cmd, Att, Clk, D, Ack> crate::asynch::PsxTrasferData
+    for PsxDevice<Dat, Cmd, Att, Clk, D, Ack>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    Ack: InputPin,
+    D: AsyncDelayNs,
+{
+    const SLEEP_NS: u32 = DRIVER_SLEEP;
+
+    async fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.wires.cmd.set_high().map_err(DeviceError::pin)?;
+        self.wires.clk.set_high().map_err(DeviceError::pin)?;
+        self.delay.delay_ns(self.timing.half_cycle_ns).await;
+
+        self.wires.att.set_low().map_err(DeviceError::pin)?; // low enable device
+        self.delay.delay_ns(self.timing.byte_gap_ns).await;
+        Ok(())
+    }
+
+    async fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        self.wires.att.set_high().map_err(DeviceError::pin)?; //high disable device
+        self.delay.delay_ns(self.timing.byte_gap_ns).await;
+        Ok(())
+    }
+
+    async fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
         let mut answer: u8 = 0;
         for i in 0..8 {
             let bit: u8 = 1 << i;
             let val: bool = (byte & bit) > 0;
-            self.wires.cmd.set_state(val.into()).unwrap_or(());
-            self.wires.clk.set_low().unwrap_or(());
-            // self.delay.delay_ns(self.half_cycle_ns);
-            self.delay_ns(CTRL_CLK);
-            if self.wires.dat.is_high().unwrap_or(false) {
+            self.wires
+                .cmd
+                .set_state(val.into())
+                .map_err(DeviceError::pin)?;
+            self.wires.clk.set_low().map_err(DeviceError::pin)?;
+            self.delay.delay_ns(self.timing.half_cycle_ns).await;
+            if self.wires.dat.is_high().map_err(DeviceError::pin)? {
                 answer |= bit;
             }
-            self.wires.clk.set_high().unwrap_or(());
-            // self.delay.delay_ns(self.half_cycle_ns);
-            self.delay_ns(CTRL_CLK);
+            self.wires.clk.set_high().map_err(DeviceError::pin)?;
+            self.delay.delay_ns(self.timing.half_cycle_ns).await;
         }
-        self.wires.cmd.set_high().unwrap_or(());
-        // self.delay.delay_ns(self.half_cycle_ns << 1);
-        self.delay_ns(CTRL_8BIT);
-        answer
+        self.wires.cmd.set_high().map_err(DeviceError::pin)?;
+        self.last_ack = self.wait_ack_async().await?;
+        Ok(answer)
+    }
+
+    fn last_ack(&self) -> Option<bool> {
+        self.last_ack
+    }
+}
+
+#[cfg(feature = "async")]
+impl<Spi, Att, D> AsyncDelayNs for SpiPsxDevice<Spi, Att, D>
+where
+    D: AsyncDelayNs,
+{
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay.delay_ns(ns).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<Spi, Att, D> crate::asynch::PsxTrasferData for SpiPsxDevice<Spi, Att, D>
+where
+    Spi: AsyncSpiDevice,
+    Att: OutputPin,
+    D: AsyncDelayNs,
+{
+    const SLEEP_NS: u32 = DRIVER_SLEEP;
+
+    async fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.att.set_low().map_err(DeviceError::pin)?; // low enable device
+        self.delay.delay_ns(SPI_BYTE_GAP).await;
+        Ok(())
+    }
+
+    async fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        self.att.set_high().map_err(DeviceError::pin)?; //high disable device
+        self.delay.delay_ns(SPI_BYTE_GAP).await;
+        Ok(())
+    }
+
+    async fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        let mut buf = [byte.reverse_bits()];
+        self.spi
+            .transfer_in_place(&mut buf)
+            .await
+            .map_err(DeviceError::spi)?;
+        let answer = buf[0].reverse_bits();
+        self.delay.delay_ns(SPI_BYTE_GAP).await;
+        Ok(answer)
     }
 }
@@ -9,242 +9,462 @@ use crate::device::PsxTrasferData;
 // const CMD_MAIN_POLL: &[u8] = &[0x01, 0x42, 0x00];
 
 /// Trait implementing a protocol for exchanging commands and data with a device
-pub trait Driver {
+pub trait Driver: ErrorType {
     /// Initialize controller
-    fn initialize(&mut self) -> CResult<()>;
+    fn initialize(&mut self) -> CResult<(), Self::Error>;
+    /// Check presence of device by a single query, without waiting
+    fn probe(&mut self) -> CResult<(), Self::Error>;
     /// Polling controller state
-    fn poll(&mut self) -> CResult<u16>;
+    fn poll(&mut self) -> CResult<u16, Self::Error>;
     /// Polling controller state and vibrate
-    fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16>;
+    fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16, Self::Error>;
     /// Polling controller state and vibrate
-    fn poll_vibrate_ex(&mut self, m1: u8, m2: u8) -> CResult<u16>;
+    fn poll_vibrate_ex(&mut self, m1: u8, m2: u8) -> CResult<u16, Self::Error>;
     /// Get device status (mode, led)
-    fn query_model_and_mode(&mut self) -> CResult<(u8, u8)>;
+    fn query_model_and_mode(&mut self) -> CResult<(u8, u8), Self::Error>;
     /// Read device info
-    /// return 3 unknown consts, 10b, 5b and 10b sizes
-    fn read_device_info(&mut self) -> CResult<DeviceCInfo>;
+    /// return 3 unknown consts, 10b, 5b and 10b sizes, and the poll mask
+    fn read_device_info(&mut self) -> CResult<DeviceCInfo, Self::Error>;
+    /// Apply settings in config mode
+    fn setup(&mut self, config: &DeviceConfig) -> CResult<(), Self::Error>;
     /// Return analog sticks values
     fn analog_sticks(&self) -> AnalogSticks;
+    /// Return button pressures, if the last response contains them
+    fn button_pressures(&self) -> Option<ButtonPressures>;
     /// Read current device mode
     fn current_mode(&self) -> DeviceMode;
+    /// Read ID of the last response
+    fn current_id(&self) -> u8;
+    /// Pause added to the pause between packets
+    fn set_extra_sleep(&mut self, ns: u32);
 }
 
-/// Implementation [`Driver`] trait for [`PsxDevice`] type
-pub struct PsxDriver<Dev> {
+/// Single packet of the protocol
+#[derive(Copy, Clone)]
+pub(crate) enum Packet {
+    /// Presence query, any failure except transport is [`ControllerError::NoResponse`]
+    Probe,
+    /// Enter (true) or exit config mode
+    Config(bool),
+    QueryModel,
+    SetModeAndLock([u8; 2]),
+    MapMotors([u8; 6]),
+    SetupPoll([u8; 3]),
+    /// Part of [`DEVICE_INFO_PARTS`]
+    DeviceInfo(usize),
+    Poll,
+    PollVibrate(u8, u8),
+}
+
+/// Largest payload sent before the fill bytes, `MapMotors`
+const MAX_PAYLOAD: usize = 6;
+const HEADER: usize = DATA_HEADER_SIZE as usize;
+
+/// Packets of [`Driver::setup`], sent in config mode
+pub(crate) fn setup_packets(config: &DeviceConfig) -> impl Iterator<Item = Packet> {
+    let mode = config.tx_mode_and_lock().map(Packet::SetModeAndLock);
+    mode.into_iter().chain([
+        Packet::MapMotors(config.tx_motors()),
+        Packet::SetupPoll(config.tx_poll_mask()),
+    ])
+}
+
+/// Packets of [`Driver::read_device_info`], sent in config mode
+pub(crate) fn device_info_packets() -> impl Iterator<Item = Packet> {
+    (0..DEVICE_INFO_PARTS.len()).map(Packet::DeviceInfo)
+}
+
+/// Protocol state of one packet shared by the blocking and async drivers,
+/// they only move the bytes through the transport and wait the pauses
+pub(crate) struct Exchange {
     buf: DeviceBuffer,
-    dev: Dev,
     cursor: usize,
+    tx: [u8; HEADER + MAX_PAYLOAD],
+    tx_len: usize,
+    fill: u8,
+    extra_sleep_ns: u32,
 }
 
-impl<Dev: PsxTrasferData> PsxDriver<Dev> {
-    pub fn new(dev: Dev) -> Self {
-        let buf = DeviceBuffer::default();
+impl Exchange {
+    pub fn new() -> Self {
         Self {
-            buf,
-            dev,
+            buf: DeviceBuffer::default(),
             cursor: 0,
+            tx: [0u8; HEADER + MAX_PAYLOAD],
+            tx_len: 0,
+            fill: TX_PS2,
+            extra_sleep_ns: 0,
         }
     }
-
-    // Wait first successs answer form device
-    fn wait_response(&mut self, retry: u8) -> CResult<()> {
-        for _ in 0..retry {
-            self.dev.sleep();
-            match self.send_query_ds2() {
-                Ok(_) => return Ok(()),
-                _ => {}
+    /// Start the packet, the response is received into the buffer
+    pub fn begin(&mut self, packet: Packet) {
+        match packet {
+            Packet::Probe => self.set(Command::QueryDS2AnalogMode, &[], TX_PS2),
+            Packet::Config(enter) => self.set(Command::Config, &[enter as u8], TX_PSX),
+            Packet::QueryModel => self.set(Command::QueryModelAndMode, &[], TX_PS2),
+            Packet::SetModeAndLock(mode) => self.set(Command::SetModeAndLock, &mode, TX_PSX),
+            Packet::MapMotors(motors) => self.set(Command::MapMotors, &motors, TX_PSX),
+            Packet::SetupPoll(mask) => self.set(Command::SetupPoll, &mask, TX_PSX),
+            Packet::DeviceInfo(n) => {
+                let (ncmd, index) = DEVICE_INFO_PARTS[n];
+                self.set(ncmd, &[index], TX_PS2)
+            }
+            Packet::Poll => self.set(Command::MainPoll, &[], TX_PS2),
+            Packet::PollVibrate(m1, m2) => {
+                let motors = [m1, m2].map(DeviceBuffer::tx_normolize_motor);
+                self.set(Command::MainPoll, &motors, TX_PSX)
             }
         }
-        Err(ControllerError::NoResponse)
     }
-    // Simple command
-    fn send_query_ds2(&mut self) -> CResult<()> {
-        self.send_command(Command::QueryDS2AnalogMode, |me| {
-            me.send_bytes(TX_PS2, me.rx_data_rest_len())
-        })
+    /// Next byte of CMD, `None` when the response is complete.
+    /// The device did not acknowledge the previous byte, the packet is over
+    pub fn next<E>(&self, ack: Option<bool>) -> CResult<Option<u8>, E> {
+        let len = match self.cursor < HEADER {
+            true => HEADER,
+            false => HEADER + self.buf.rx_data_len() as usize,
+        };
+        if self.cursor >= len {
+            return Ok(None);
+        }
+        if self.cursor > 0 && Some(false) == ack {
+            return Err(ControllerError::NoResponse);
+        }
+        match self.cursor < self.tx_len {
+            true => Ok(Some(self.tx[self.cursor])),
+            false => Ok(Some(self.fill)),
+        }
+    }
+    /// Store the byte of DAT, the header is checked when it is complete
+    pub fn receive<E>(&mut self, byte: u8) -> CResult<(), E> {
+        self.buf.data[self.cursor] = byte;
+        self.cursor += 1;
+        match HEADER == self.cursor && !self.buf.rx_is_header_success() {
+            true => Err(ControllerError::BadHeader),
+            false => Ok(()),
+        }
+    }
+    /// Result of the packet, any failure of the probe except transport is no response
+    pub fn finish<E>(packet: Packet, result: CResult<(), E>) -> CResult<(), E> {
+        match (packet, result) {
+            (Packet::Probe, Err(ControllerError::Transport(e))) => {
+                Err(ControllerError::Transport(e))
+            }
+            (Packet::Probe, Err(_)) => Err(ControllerError::NoResponse),
+            (_, result) => result,
+        }
+    }
+    /// Data of the last response
+    pub fn buffer(&self) -> &DeviceBuffer {
+        &self.buf
+    }
+    pub fn extra_sleep_ns(&self) -> u32 {
+        self.extra_sleep_ns
+    }
+    pub fn set_extra_sleep(&mut self, ns: u32) {
+        self.extra_sleep_ns = ns;
+    }
+    pub fn analog_sticks(&self) -> AnalogSticks {
+        if self.buf.rx_is_analog_mode() {
+            self.buf.rx_analog_sticks()
+        } else {
+            AnalogSticks::default()
+        }
+    }
+    pub fn button_pressures(&self) -> Option<ButtonPressures> {
+        self.buf.rx_pressures()
+    }
+    pub fn current_mode(&self) -> DeviceMode {
+        self.buf.rx_data_id().into()
+    }
+    pub fn current_id(&self) -> u8 {
+        self.buf.data[1]
     }
-    /// Enter/Escape in config mode, closure using for send commands in config mode
-    fn configure<F, T>(&mut self, f_cfg: F) -> CResult<T>
-    where
-        F: Fn(&mut Self) -> CResult<T>,
-    {
-        self.dev.sleep();
-        self.send_command(Command::Config, |me| {
-            me.send_byte(0x01);
-            me.send_bytes(TX_PSX, me.rx_data_rest_len());
-        })?;
-        self.dev.sleep();
 
-        let result = (f_cfg)(self);
+    fn set(&mut self, ncmd: Command, payload: &[u8], fill: u8) {
+        self.cursor = 0;
+        self.tx[..HEADER].copy_from_slice(&[0x01, ncmd as u8, 0x00]);
+        self.tx[HEADER..HEADER + payload.len()].copy_from_slice(payload);
+        self.tx_len = HEADER + payload.len();
+        self.fill = fill;
+    }
+}
 
-        self.dev.sleep();
-        self.send_command(Command::Config, |me| {
-            me.send_byte(0x00);
-            me.send_bytes(TX_PSX, me.rx_data_rest_len());
-        })?;
-        self.dev.sleep();
+/// Request of [`Sequence`] to the driver
+pub(crate) enum Action {
+    /// Pause between packets
+    Sleep,
+    /// Send the packet and pass the result to [`Sequence::complete`]
+    Send(Packet),
+    /// Sequence is over, [`Sequence::finish`] has the result
+    Done,
+}
+
+#[derive(Copy, Clone)]
+enum Stage {
+    /// Probe until the device answers, attempts left
+    Wait(u8),
+    Probe,
+    Enter,
+    Packets,
+    Exit,
+    Done,
+}
+
+/// Order of packets and pauses of a driver method shared by the blocking and async drivers:
+/// retries of the probe and the exit from config mode after a failed packet.
+/// The driver only performs the actions
+pub(crate) struct Sequence<I, E> {
+    stage: Stage,
+    packets: I,
+    is_sleep: bool,
+    result: CResult<(), E>,
+}
 
-        result
+impl<E> Sequence<core::iter::Empty<Packet>, E> {
+    /// Probe up to `retry` times, a transport failure stops at once
+    pub fn wait_response(retry: u8) -> Self {
+        Self::new(Stage::Wait(retry), core::iter::empty(), true)
     }
-    /// Send command sequence of bytes, closure will call for send command payload
-    fn send_command<T>(&mut self, ncmd: Command, f_send_data: T) -> CResult<()>
-    where
-        T: Fn(&mut Self),
-    {
-        self.dev.start_cmd();
-        self.send_header(ncmd);
-        if self.buf.rx_is_header_success() {
-            (f_send_data)(self);
-            self.dev.stop_cmd();
-            // self.dbg_cmd_drop(ncmd);
-            Ok(())
-        } else {
-            self.dev.stop_cmd();
-            // self.dbg_cmd_drop(ncmd);
-            Err(ControllerError::BadHeader)
+    /// Single probe without waiting
+    pub fn probe() -> Self {
+        Self::new(Stage::Probe, core::iter::empty(), false)
+    }
+}
+
+impl<I: Iterator<Item = Packet>, E> Sequence<I, E> {
+    /// Packets in config mode, config mode is left even if a packet fails
+    pub fn configure(packets: I) -> Self {
+        Self::new(Stage::Enter, packets, true)
+    }
+    fn new(stage: Stage, packets: I, is_sleep: bool) -> Self {
+        Self {
+            stage,
+            packets,
+            is_sleep,
+            result: Ok(()),
         }
     }
-    fn send_header(&mut self, ncmd: Command) {
-        self.cursor = 0;
-        self.__tx_rx__(0x01);
-        self.__tx_rx__(ncmd as u8);
-        self.__tx_rx__(0x00);
+    pub fn next(&mut self) -> Action {
+        if core::mem::take(&mut self.is_sleep) {
+            return Action::Sleep;
+        }
+        match self.stage {
+            Stage::Wait(0) => self.fail(ControllerError::NoResponse),
+            Stage::Wait(_) | Stage::Probe => Action::Send(Packet::Probe),
+            Stage::Enter => Action::Send(Packet::Config(true)),
+            Stage::Packets => match self.packets.next() {
+                Some(packet) => Action::Send(packet),
+                None => {
+                    self.stage = Stage::Exit;
+                    Action::Send(Packet::Config(false))
+                }
+            },
+            Stage::Exit => Action::Send(Packet::Config(false)),
+            Stage::Done => Action::Done,
+        }
+    }
+    /// Result of the last sent packet, returns true if the response is read by the caller
+    pub fn complete(&mut self, result: CResult<(), E>) -> bool {
+        let stage = self.stage;
+        let is_response = result.is_ok() && matches!(stage, Stage::Packets);
+        self.is_sleep = true;
+        match (stage, result) {
+            (Stage::Wait(_), Err(ControllerError::Transport(e))) => {
+                self.fail(ControllerError::Transport(e));
+            }
+            (Stage::Wait(n), Err(_)) => {
+                self.stage = Stage::Wait(n - 1);
+                self.is_sleep = n > 1;
+            }
+            (Stage::Enter, Ok(_)) => self.stage = Stage::Packets,
+            (Stage::Packets, Err(e)) => {
+                self.result = Err(e);
+                self.stage = Stage::Exit;
+            }
+            (Stage::Packets, Ok(_)) => {}
+            (_, Ok(_)) => self.stage = Stage::Done,
+            (_, Err(e)) => {
+                self.fail(e);
+            }
+        }
+        is_response
+    }
+    /// Result of the sequence after [`Action::Done`]
+    pub fn finish(self) -> CResult<(), E> {
+        self.result
+    }
+
+    fn fail(&mut self, error: ControllerError<E>) -> Action {
+        self.result = Err(error);
+        self.stage = Stage::Done;
+        self.is_sleep = false;
+        Action::Done
+    }
+}
+
+impl DeviceCInfo {
+    /// Store the response of [`Packet::DeviceInfo`]
+    pub(crate) fn read_part(&mut self, packet: Packet, buf: &DeviceBuffer) {
+        if let Packet::DeviceInfo(n) = packet {
+            self.set_part(n, &buf.data[4..]);
+        }
+    }
+}
+
+/// Implementation [`Driver`] trait for [`PsxDevice`] type
+pub struct PsxDriver<Dev> {
+    exchange: Exchange,
+    dev: Dev,
+}
+
+impl<Dev: PsxTrasferData> PsxDriver<Dev> {
+    pub fn new(dev: Dev) -> Self {
+        Self {
+            exchange: Exchange::new(),
+            dev,
+        }
+    }
+
+    /// Send one packet without pauses, the caller keeps the pause between packets
+    pub(crate) fn transfer(&mut self, packet: Packet) -> CResult<(), Dev::Error> {
+        self.dev.start_cmd().map_err(ControllerError::Transport)?;
+        self.exchange.begin(packet);
+        let result = self.exchange_bytes();
+        self.dev.stop_cmd().map_err(ControllerError::Transport)?;
+        Exchange::finish(packet, result)
+    }
+    /// Data of the last response
+    pub(crate) fn buffer(&self) -> &DeviceBuffer {
+        self.exchange.buffer()
+    }
+    /// Transport of the driver
+    pub(crate) fn device(&self) -> &Dev {
+        &self.dev
+    }
+    pub(crate) fn device_mut(&mut self) -> &mut Dev {
+        &mut self.dev
     }
-    fn send_byte(&mut self, byte: u8) {
-        self.__tx_rx__(byte);
+    /// Wrap the transport, the state of the exchange is kept
+    pub(crate) fn map_device<T: PsxTrasferData>(self, f: impl FnOnce(Dev) -> T) -> PsxDriver<T> {
+        PsxDriver {
+            exchange: self.exchange,
+            dev: f(self.dev),
+        }
     }
-    fn send_bytes(&mut self, byte: u8, repeat: u8) {
-        for _ in 0..repeat {
-            self.__tx_rx__(byte);
+
+    fn exchange_bytes(&mut self) -> CResult<(), Dev::Error> {
+        while let Some(byte) = self.exchange.next(self.dev.last_ack())? {
+            let rx = self
+                .dev
+                .send_8bit(byte)
+                .map_err(ControllerError::Transport)?;
+            self.exchange.receive(rx)?;
         }
+        Ok(())
     }
-    fn __tx_rx__(&mut self, byte: u8) {
-        if self.cursor >= DATA_SIZE {
-            panic!("Firmware error");
+    /// Pause between packets, longer for models with quirks
+    fn sleep(&mut self) {
+        self.dev.sleep();
+        if self.exchange.extra_sleep_ns() > 0 {
+            self.dev.delay_ns(self.exchange.extra_sleep_ns());
         }
-        //self.cbuf[self.data_cursor] = byte;
-        self.buf.data[self.cursor] = self.dev.send_8bit(byte);
-        self.cursor += 1;
     }
-    fn rx_data_rest_len(&self) -> u8 {
-        let len = self.buf.rx_data_len();
-        let len_sended = self.cursor as u8 - DATA_HEADER_SIZE;
-        if len_sended >= len {
-            return 0;
+    /// Perform the actions of the sequence, `on_response` reads the response of every
+    /// packet passed to the sequence
+    fn run<F>(
+        &mut self,
+        mut seq: Sequence<impl Iterator<Item = Packet>, Dev::Error>,
+        mut on_response: F,
+    ) -> CResult<(), Dev::Error>
+    where
+        F: FnMut(Packet, &DeviceBuffer),
+    {
+        loop {
+            match seq.next() {
+                Action::Sleep => self.sleep(),
+                Action::Send(packet) => {
+                    let result = self.transfer(packet);
+                    if seq.complete(result) {
+                        on_response(packet, self.exchange.buffer());
+                    }
+                }
+                Action::Done => return seq.finish(),
+            }
         }
-        len - len_sended
     }
 }
 
+impl<Dev: ErrorType> ErrorType for PsxDriver<Dev> {
+    type Error = Dev::Error;
+}
+
 impl<Dev> Driver for PsxDriver<Dev>
 where
     Dev: PsxTrasferData,
 {
     /// Initialize controller
-    fn initialize(&mut self) -> CResult<()> {
-        self.wait_response(10)?;
-        //self.configure(|_| Ok(()));
-        self.dev.sleep();
-        Ok(())
+    fn initialize(&mut self) -> CResult<(), Dev::Error> {
+        self.run(Sequence::wait_response(10), |_, _| {})
+    }
+    fn probe(&mut self) -> CResult<(), Dev::Error> {
+        self.run(Sequence::probe(), |_, _| {})
     }
     /// Polling controller state
-    fn poll(&mut self) -> CResult<u16> {
-        self.send_command(Command::MainPoll, |me| {
-            me.send_bytes(TX_PS2, me.rx_data_rest_len());
-        })?;
-        Ok(self.buf.rx_buttons())
+    fn poll(&mut self) -> CResult<u16, Dev::Error> {
+        self.transfer(Packet::Poll)?;
+        Ok(self.buffer().rx_buttons())
     }
 
-    fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16> {
-        self.send_command(Command::MainPoll, |me| {
-            me.send_byte(DeviceBuffer::tx_normolize_motor(m1));
-            me.send_byte(DeviceBuffer::tx_normolize_motor(m2));
-            me.send_bytes(TX_PSX, me.rx_data_rest_len());
-        })?;
-        Ok(self.buf.rx_buttons())
+    fn poll_vibrate(&mut self, m1: u8, m2: u8) -> CResult<u16, Dev::Error> {
+        self.transfer(Packet::PollVibrate(m1, m2))?;
+        Ok(self.buffer().rx_buttons())
+    }
+
+    fn poll_vibrate_ex(&mut self, m1: u8, m2: u8) -> CResult<u16, Dev::Error> {
+        self.poll_vibrate(m1, m2)
+    }
+
+    fn query_model_and_mode(&mut self) -> CResult<(u8, u8), Dev::Error> {
+        let mut model = (0, 0);
+        self.run(
+            Sequence::configure([Packet::QueryModel].into_iter()),
+            |_, buf| {
+                model = (buf.rx_data_model(), buf.rx_data_mode());
+            },
+        )?;
+        Ok(model)
     }
 
-    fn poll_vibrate_ex(&mut self, m1: u8, m2: u8) -> CResult<u16> {
-        self.send_command(Command::MainPoll, |me| {
-            me.send_byte(DeviceBuffer::tx_normolize_motor(m1));
-            me.send_byte(DeviceBuffer::tx_normolize_motor(m2));
-            me.send_bytes(TX_PSX, me.rx_data_rest_len());
+    fn read_device_info(&mut self) -> CResult<DeviceCInfo, Dev::Error> {
+        let mut info = DeviceCInfo::default();
+        self.run(Sequence::configure(device_info_packets()), |packet, buf| {
+            info.read_part(packet, buf)
         })?;
-        Ok(self.buf.rx_buttons())
-    }
-
-    fn query_model_and_mode(&mut self) -> CResult<(u8, u8)> {
-        self.configure(|me| {
-            me.send_command(Command::QueryModelAndMode, |drv| {
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            Ok((me.buf.rx_data_model(), me.buf.rx_data_mode()))
-        })
-    }
-
-    fn read_device_info(&mut self) -> CResult<DeviceCInfo> {
-        self.configure(|me| {
-            let mut data = DeviceCInfo::default();
-            // read unknown 1 part 1
-            me.send_command(Command::GetConst1, |drv| {
-                drv.send_byte(0x00u8);
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            me.dev.sleep();
-            for i in 0..5 {
-                data.unknown1[i] = me.buf.data[i + 4];
-            }
-            // read unknown 1 part 2
-            me.send_command(Command::GetConst1, |drv| {
-                drv.send_byte(0x01u8);
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            me.dev.sleep();
-            for i in 0..5 {
-                data.unknown1[i + 5] = me.buf.data[i + 4];
-            }
-            // read unknown 2 part 1
-            me.send_command(Command::GetConst2, |drv| {
-                drv.send_byte(0x00u8);
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            me.dev.sleep();
-            for i in 0..5 {
-                data.unknown2[i] = me.buf.data[i + 4];
-            }
-            // read unknown 3 part 1
-            me.send_command(Command::GetConst3, |drv| {
-                drv.send_byte(0x00u8);
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            me.dev.sleep();
-            for i in 0..5 {
-                data.unknown3[i] = me.buf.data[i + 4];
-            }
-            // read unknown 3 part 2
-            me.send_command(Command::GetConst3, |drv| {
-                drv.send_byte(0x01u8);
-                drv.send_bytes(TX_PS2, drv.rx_data_rest_len());
-            })?;
-            me.dev.sleep();
-            for i in 0..5 {
-                data.unknown3[i + 5] = me.buf.data[i + 4];
-            }
-            //
-            Ok(data)
-        })
+        Ok(info)
+    }
+
+    fn setup(&mut self, config: &DeviceConfig) -> CResult<(), Dev::Error> {
+        self.run(Sequence::configure(setup_packets(config)), |_, _| {})
     }
 
     fn analog_sticks(&self) -> AnalogSticks {
-        if self.buf.rx_is_analog_mode() {
-            self.buf.rx_analog_sticks()
-        } else {
-            AnalogSticks::default()
-        }
+        self.exchange.analog_sticks()
+    }
+
+    fn button_pressures(&self) -> Option<ButtonPressures> {
+        self.exchange.button_pressures()
     }
 
     fn current_mode(&self) -> DeviceMode {
-        self.buf.rx_data_id().into()
+        self.exchange.current_mode()
+    }
+
+    fn current_id(&self) -> u8 {
+        self.exchange.current_id()
+    }
+
+    fn set_extra_sleep(&mut self, ns: u32) {
+        self.exchange.set_extra_sleep(ns);
     }
 }
 
@@ -254,50 +474,64 @@ pub struct DeviceBuffer {
 }
 
 impl DeviceBuffer {
+    /// Response of a pad passed by another device (multitap slot), `slot` starts at the ID
+    pub(crate) fn from_slot(slot: &[u8]) -> Self {
+        let mut buf = Self::default();
+        buf.data[0] = 0xFF;
+        buf.data[1..=slot.len()].copy_from_slice(slot);
+        buf
+    }
     /// function rx_data_id, RX data[1] - ID
     /// 0x4w => digital mode
     /// 0x7w => analog mode
     /// 0xFw => config mode
     /// where w is data len in u16
-    fn rx_data_id(&self) -> u8 {
+    pub(crate) fn rx_data_id(&self) -> u8 {
         // upper nibble is the ID of the peripheral
         0xF0 & self.data[1]
     }
-    fn rx_data_word_count(&self) -> u8 {
+    pub(crate) fn rx_data_word_count(&self) -> u8 {
         // lower nibble is the output size in u16 (1, 3 or 9)
         0x0F & self.data[1]
     }
-    fn rx_data_model(&self) -> u8 {
+    pub(crate) fn rx_data_model(&self) -> u8 {
         self.data[3]
     }
-    fn rx_data_mode(&self) -> u8 {
+    pub(crate) fn rx_data_mode(&self) -> u8 {
         self.data[5]
     }
-    fn rx_analog_sticks(&self) -> AnalogSticks {
+    pub(crate) fn rx_analog_sticks(&self) -> AnalogSticks {
         AnalogSticks::new(self.data[7], self.data[8], self.data[5], self.data[6])
     }
-    fn rx_data_len(&self) -> u8 {
+    pub(crate) fn rx_pressures(&self) -> Option<ButtonPressures> {
+        if self.rx_is_analog_mode() && 9 == self.rx_data_word_count() {
+            let mut data = [0u8; 12];
+            data.copy_from_slice(&self.data[9..21]);
+            Some(ButtonPressures::new(data))
+        } else {
+            None
+        }
+    }
+    pub(crate) fn rx_data_len(&self) -> u8 {
         match self.rx_data_word_count() {
             1 => 2,
             3 => 6,
             _ => 18,
         }
     }
-    fn rx_is_header_success(&self) -> bool {
+    pub(crate) fn rx_is_header_success(&self) -> bool {
         let wc = self.rx_data_word_count();
-        if wc != 1 && wc != 3 && wc != 9 {
+        if !matches!(wc, 1 | 3 | 9) {
             return false;
         }
         0xFF == self.data[0] && self.rx_is_any_mode()
     }
-    fn rx_is_any_mode(&self) -> bool {
-        match self.rx_data_id() {
-            0x40 | 0x70 | 0xF0 => true,
-            _ => false,
-        }
+    pub(crate) fn rx_is_any_mode(&self) -> bool {
+        // 0x2w - neGcon, 0xEw - Jogcon in jog mode, mouse 0x1w is read by `PsxMouse` only
+        matches!(self.rx_data_id(), 0x20 | 0x40 | 0x70 | 0xE0 | 0xF0)
     }
 
-    fn rx_is_analog_mode(&self) -> bool {
+    pub(crate) fn rx_is_analog_mode(&self) -> bool {
         0x70 == self.rx_data_id()
     }
     // fn rx_is_config_mode(&self) -> bool {
@@ -309,13 +543,13 @@ impl DeviceBuffer {
     // fn is_still_in_config_mode(&self) -> bool {
     //     self.rx_is_config_mode() && !self.rx_is_mode_changed()
     // }
-    fn rx_buttons(&self) -> u16 {
+    pub(crate) fn rx_buttons(&self) -> u16 {
         ((self.data[4] as u16) << 8) | (self.data[3] as u16)
     }
     // fn rx_is_dual_shock_native_mode(&self) -> bool {
     //     0xFF == self.data[3] && 0xFF == self.data[4]
     // }
-    fn tx_normolize_motor(motor_value: u8) -> u8 {
+    pub(crate) fn tx_normolize_motor(motor_value: u8) -> u8 {
         match motor_value {
             0x00 => 0x00,
             _ => (0x40 + (motor_value as u16) * (0xff - 0x40) / 0xff) as u8,
@@ -329,3 +563,60 @@ impl Default for DeviceBuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Result = CResult<(), ()>;
+
+    /// Perform the sequence, `results` answer the packets in order and the rest succeed.
+    /// Returns the numbers of packets and pauses, the last packet and the result
+    fn run<I>(mut seq: Sequence<I, ()>, results: &[Result]) -> (usize, usize, Packet, Result)
+    where
+        I: Iterator<Item = Packet>,
+    {
+        let (mut sent, mut sleeps, mut last) = (0, 0, Packet::Poll);
+        loop {
+            match seq.next() {
+                Action::Sleep => sleeps += 1,
+                Action::Send(packet) => {
+                    seq.complete(results.get(sent).copied().unwrap_or(Ok(())));
+                    sent += 1;
+                    last = packet;
+                }
+                Action::Done => return (sent, sleeps, last, seq.finish()),
+            }
+        }
+    }
+
+    #[test]
+    fn wait_response_retries() {
+        let no = Err(ControllerError::NoResponse);
+        let (sent, sleeps, _, result) = run(Sequence::wait_response(3), &[no, Ok(())]);
+        assert_eq!((2, 3, Ok(())), (sent, sleeps, result));
+        let (sent, sleeps, _, result) = run(Sequence::wait_response(3), &[no, no, no]);
+        assert_eq!((3, 3, no), (sent, sleeps, result));
+        // transport failure is not retried
+        let fault = Err(ControllerError::Transport(()));
+        let (sent, _, _, result) = run(Sequence::wait_response(3), &[fault]);
+        assert_eq!((1, fault), (sent, result));
+    }
+
+    #[test]
+    fn configure_exits_after_failure() {
+        let packets = [Packet::QueryModel; 3];
+        let (sent, sleeps, _, result) = run(Sequence::configure(packets.into_iter()), &[]);
+        assert_eq!((5, 6, Ok(())), (sent, sleeps, result));
+
+        let bad = Err(ControllerError::BadHeader);
+        let (sent, sleeps, last, result) =
+            run(Sequence::configure(packets.into_iter()), &[Ok(()), bad]);
+        // enter, the failed packet and exit
+        assert_eq!((3, 4, bad), (sent, sleeps, result));
+        assert!(matches!(last, Packet::Config(false)));
+        // config mode was not entered
+        let (sent, sleeps, _, result) = run(Sequence::configure(packets.into_iter()), &[bad]);
+        assert_eq!((1, 1, bad), (sent, sleeps, result));
+    }
+}
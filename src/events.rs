@@ -0,0 +1,237 @@
+use crate::buttons::{Button, BUTTONS};
+
+/// Capacity of the event queue
+pub const EVENT_QUEUE_SIZE: usize = 32;
+
+/// Button event
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ButtonEvent {
+    /// Button changed state from up to down
+    Pressed(Button),
+    /// Button changed state from down to up
+    Released(Button),
+    /// Button is down longer than hold threshold, duration in ms
+    Held(Button, u32),
+    /// Auto-repeat of the held button
+    Repeat(Button),
+}
+
+/// Thresholds of events in ms, 0 disables the event
+#[derive(Copy, Clone, PartialEq)]
+pub struct EventConfig {
+    /// Time to emit [`ButtonEvent::Held`]
+    pub hold_ms: u32,
+    /// Time to first [`ButtonEvent::Repeat`]
+    pub repeat_delay_ms: u32,
+    /// Time between next [`ButtonEvent::Repeat`]
+    pub repeat_interval_ms: u32,
+}
+impl Default for EventConfig {
+    fn default() -> Self {
+        Self {
+            hold_ms: 500,
+            repeat_delay_ms: 500,
+            repeat_interval_ms: 100,
+        }
+    }
+}
+
+/// Fixed capacity queue, the oldest items are dropped on overflow
+pub(crate) struct Queue<T, const N: usize> {
+    items: [Option<T>; N],
+    head: usize,
+    len: usize,
+    dropped: u32,
+}
+impl<T: Copy, const N: usize> Queue<T, N> {
+    pub fn new() -> Self {
+        Self {
+            items: [None; N],
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+    pub fn push(&mut self, item: T) {
+        if self.len == N {
+            self.pop();
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        self.items[(self.head + self.len) % N] = Some(item);
+        self.len += 1;
+    }
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+    /// Number of items dropped on overflow
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+/// Button events generated from polled buttons
+pub(crate) struct ButtonEvents {
+    config: EventConfig,
+    queue: Queue<ButtonEvent, EVENT_QUEUE_SIZE>,
+    buttons: u16,
+    pressed_at: [u32; 16],
+    repeat_at: [u32; 16],
+    held: u16,
+}
+impl ButtonEvents {
+    pub fn new() -> Self {
+        Self {
+            config: EventConfig::default(),
+            queue: Queue::new(),
+            buttons: 0xFFFF,
+            pressed_at: [0; 16],
+            repeat_at: [0; 16],
+            held: 0,
+        }
+    }
+    pub fn set_config(&mut self, config: EventConfig) {
+        self.config = config;
+    }
+    /// Compare buttons (active low) with previous state at time `now_ms`
+    pub fn update(&mut self, buttons: u16, now_ms: u32) {
+        let changed = self.buttons ^ buttons;
+        let down = !buttons;
+        for (i, btn) in BUTTONS.iter().enumerate() {
+            let bit = *btn as u16;
+            if (changed & bit) > 0 {
+                if (down & bit) > 0 {
+                    self.pressed_at[i] = now_ms;
+                    self.repeat_at[i] = now_ms.wrapping_add(self.config.repeat_delay_ms);
+                    self.push(ButtonEvent::Pressed(*btn));
+                } else {
+                    self.held &= !bit;
+                    self.push(ButtonEvent::Released(*btn));
+                }
+                continue;
+            }
+            if (down & bit) == 0 {
+                continue;
+            }
+            let duration = now_ms.wrapping_sub(self.pressed_at[i]);
+            if self.config.hold_ms > 0 && (self.held & bit) == 0 && duration >= self.config.hold_ms
+            {
+                self.held |= bit;
+                self.push(ButtonEvent::Held(*btn, duration));
+            }
+            if self.config.repeat_delay_ms > 0 && Self::is_due(now_ms, self.repeat_at[i]) {
+                let interval = self.config.repeat_interval_ms.max(1);
+                self.repeat_at[i] = now_ms.wrapping_add(interval);
+                self.push(ButtonEvent::Repeat(*btn));
+            }
+        }
+        self.buttons = buttons;
+    }
+    pub fn pop(&mut self) -> Option<ButtonEvent> {
+        self.queue.pop()
+    }
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+    /// Number of events dropped on overflow
+    pub fn dropped(&self) -> u32 {
+        self.queue.dropped()
+    }
+    fn push(&mut self, event: ButtonEvent) {
+        self.queue.push(event);
+    }
+    fn is_due(now_ms: u32, at_ms: u32) -> bool {
+        (now_ms.wrapping_sub(at_ms) as i32) >= 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(events: &mut ButtonEvents) -> ([Option<ButtonEvent>; 4], usize) {
+        let mut list = [None; 4];
+        let mut count = 0;
+        while let Some(event) = events.pop() {
+            list[count.min(3)] = Some(event);
+            count += 1;
+        }
+        (list, count)
+    }
+
+    #[test]
+    fn hold_and_repeat() {
+        let cross = !(Button::Cross as u16);
+        let mut events = ButtonEvents::new();
+        events.update(cross, 1000);
+        assert_eq!(Some(ButtonEvent::Pressed(Button::Cross)), events.pop());
+        events.update(cross, 1499);
+        assert_eq!(0, events.len());
+
+        events.update(cross, 1500);
+        let (list, count) = drain(&mut events);
+        assert_eq!(2, count);
+        assert_eq!(Some(ButtonEvent::Held(Button::Cross, 500)), list[0]);
+        assert_eq!(Some(ButtonEvent::Repeat(Button::Cross)), list[1]);
+        // held once, repeated by interval
+        events.update(cross, 1599);
+        assert_eq!(0, events.len());
+        events.update(cross, 1600);
+        assert_eq!(Some(ButtonEvent::Repeat(Button::Cross)), events.pop());
+        assert_eq!(None, events.pop());
+
+        events.update(0xFFFF, 1650);
+        assert_eq!(Some(ButtonEvent::Released(Button::Cross)), events.pop());
+    }
+
+    #[test]
+    fn disabled_thresholds() {
+        let mut events = ButtonEvents::new();
+        events.set_config(EventConfig {
+            hold_ms: 0,
+            repeat_delay_ms: 0,
+            repeat_interval_ms: 0,
+        });
+        let start = !(Button::Start as u16);
+        events.update(start, 0);
+        events.update(start, 10_000);
+        let (list, count) = drain(&mut events);
+        assert_eq!(
+            (1, Some(ButtonEvent::Pressed(Button::Start))),
+            (count, list[0])
+        );
+    }
+
+    #[test]
+    fn overflow_drops_oldest() {
+        let mut queue = Queue::<u32, 4>::new();
+        (1..=6).for_each(|i| queue.push(i));
+        assert_eq!((4, 2), (queue.len(), queue.dropped()));
+        assert_eq!(
+            [Some(3), Some(4), Some(5), Some(6), None],
+            [(); 5].map(|_| queue.pop())
+        );
+
+        // 16 presses and 16 releases fill the queue, the next presses push out the first ones
+        let mut events = ButtonEvents::new();
+        events.update(0x0000, 0);
+        events.update(0xFFFF, 10);
+        events.update(0x0000, 20);
+        assert_eq!((EVENT_QUEUE_SIZE, 16), (events.len(), events.dropped()));
+        assert_eq!(Some(ButtonEvent::Released(Button::Select)), events.pop());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::capabilities::DeviceCapabilities;
+use crate::commands::DeviceCInfo;
+
+/// Model of device identified by [`identify_device`]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceModel {
+    #[default]
+    Unknown,
+    /// DualShock SCPH-1200
+    Scph1200,
+    /// DualShock 2 SCPH-10010
+    Scph10010,
+    /// Receivers of Mad Catz and Logitech wireless pads, DualShock 2 without pressures
+    Wireless,
+    GuitarHero,
+    /// Digital pad without config mode, dance pads (DDR) and SCPH-1080 look the same on the bus
+    DancePad,
+    /// Jogcon in jog mode, ID 0xE3
+    Jogcon,
+    /// neGcon, ID 0x23
+    NeGcon,
+}
+
+/// Deviations of the model applied by the driver
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Quirks {
+    /// Added to the pause between packets
+    pub extra_sleep_ns: u32,
+    /// Pressures are not enabled even if requested
+    pub no_pressures: bool,
+    /// Device has no config mode, settings are not sent
+    pub no_config: bool,
+}
+
+/// Responses of device collected while connecting
+#[derive(Copy, Clone, Default)]
+pub struct Signature {
+    /// ID of the probe response
+    pub id: u8,
+    /// Device switched to config mode
+    pub is_config: bool,
+    /// Byte 3 of `QueryModelAndMode`
+    pub model: u8,
+    pub info: DeviceCInfo,
+}
+
+impl Signature {
+    /// Capabilities decoded from the constants, pressures of DualShock 2 are known by the model
+    /// because `QueryDS2AnalogMode` answers zeros in digital mode
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        let caps = DeviceCapabilities::from(&self.info);
+        match self.is_config {
+            true => caps.with_model(self.model),
+            false => caps,
+        }
+    }
+}
+
+/// Entry of [`FINGERPRINTS`], `None` matches any value
+#[derive(Copy, Clone)]
+pub struct Fingerprint {
+    pub model: DeviceModel,
+    /// Upper nibble of ID
+    pub id: Option<u8>,
+    pub is_config: Option<bool>,
+    pub model_byte: Option<u8>,
+    /// `GetConst1` as in [`DeviceCInfo::unknown1`]
+    pub actuators: Option<[u8; 10]>,
+    /// `GetConst3` as in [`DeviceCInfo::unknown3`]
+    pub modes: Option<[u8; 10]>,
+    pub is_pressures: Option<bool>,
+    pub quirks: Quirks,
+}
+
+impl Fingerprint {
+    const ANY: Self = Self {
+        model: DeviceModel::Unknown,
+        id: None,
+        is_config: None,
+        model_byte: None,
+        actuators: None,
+        modes: None,
+        is_pressures: None,
+        quirks: Quirks {
+            extra_sleep_ns: 0,
+            no_pressures: false,
+            no_config: false,
+        },
+    };
+
+    pub fn matches(&self, sig: &Signature) -> bool {
+        let caps = sig.capabilities();
+        fn check<T: PartialEq>(expected: Option<T>, value: T) -> bool {
+            expected.map_or(true, |v| v == value)
+        }
+        check(self.id, sig.id & 0xF0)
+            && check(self.is_config, sig.is_config)
+            && check(self.model_byte, sig.model)
+            && check(self.actuators, sig.info.unknown1)
+            && check(self.modes, sig.info.unknown3)
+            && check(self.is_pressures, caps.is_pressures())
+    }
+}
+
+/// Small motor of 100 mA switched by bit, large motor of 200 mA with 8 bit speed
+const SONY_ACTUATORS: [u8; 10] = [0x00, 0x01, 0x02, 0x00, 0x0A, 0x00, 0x01, 0x01, 0x01, 0x14];
+/// Motors of wireless receivers, the current of both is reported as zero
+const WIRELESS_ACTUATORS: [u8; 10] = [0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01, 0x00];
+/// Guitar Hero has no motors
+const NO_ACTUATORS: [u8; 10] = [0x00; 10];
+/// Digital and analog modes
+const SONY_MODES: [u8; 10] = [0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00];
+
+const NO_PRESSURES: Quirks = Quirks {
+    no_pressures: true,
+    ..Fingerprint::ANY.quirks
+};
+const NO_CONFIG: Quirks = Quirks {
+    no_config: true,
+    ..NO_PRESSURES
+};
+
+/// Known devices, the first matching entry wins
+pub const FINGERPRINTS: &[Fingerprint] = &[
+    Fingerprint {
+        model: DeviceModel::NeGcon,
+        id: Some(0x20),
+        quirks: NO_CONFIG,
+        ..Fingerprint::ANY
+    },
+    Fingerprint {
+        model: DeviceModel::Jogcon,
+        id: Some(0xE0),
+        quirks: NO_PRESSURES,
+        ..Fingerprint::ANY
+    },
+    Fingerprint {
+        model: DeviceModel::DancePad,
+        id: Some(0x40),
+        is_config: Some(false),
+        quirks: NO_CONFIG,
+        ..Fingerprint::ANY
+    },
+    Fingerprint {
+        model: DeviceModel::GuitarHero,
+        model_byte: Some(0x01),
+        actuators: Some(NO_ACTUATORS),
+        modes: Some(SONY_MODES),
+        quirks: NO_PRESSURES,
+        ..Fingerprint::ANY
+    },
+    Fingerprint {
+        model: DeviceModel::Scph10010,
+        model_byte: Some(0x03),
+        actuators: Some(SONY_ACTUATORS),
+        modes: Some(SONY_MODES),
+        is_pressures: Some(true),
+        ..Fingerprint::ANY
+    },
+    Fingerprint {
+        model: DeviceModel::Scph1200,
+        model_byte: Some(0x01),
+        actuators: Some(SONY_ACTUATORS),
+        modes: Some(SONY_MODES),
+        is_pressures: Some(false),
+        quirks: NO_PRESSURES,
+        ..Fingerprint::ANY
+    },
+    // receivers drop packets sent too soon after config mode
+    Fingerprint {
+        model: DeviceModel::Wireless,
+        model_byte: Some(0x03),
+        actuators: Some(WIRELESS_ACTUATORS),
+        modes: Some(SONY_MODES),
+        quirks: Quirks {
+            extra_sleep_ns: 4_000_000,
+            ..NO_PRESSURES
+        },
+        ..Fingerprint::ANY
+    },
+];
+
+/// Entry of [`FINGERPRINTS`] matching the device, unknown devices have no quirks
+pub fn identify_device(sig: &Signature) -> &'static Fingerprint {
+    FINGERPRINTS
+        .iter()
+        .find(|fp| fp.matches(sig))
+        .unwrap_or(&Fingerprint::ANY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(model: u8, actuators: [u8; 10]) -> Signature {
+        Signature {
+            id: 0xF3,
+            is_config: true,
+            model,
+            info: DeviceCInfo {
+                unknown1: actuators,
+                unknown3: SONY_MODES,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn identifies_sony_pads() {
+        let fp = identify_device(&signature(0x03, SONY_ACTUATORS));
+        assert_eq!(DeviceModel::Scph10010, fp.model);
+        assert_eq!(Quirks::default(), fp.quirks);
+        let fp = identify_device(&signature(0x01, SONY_ACTUATORS));
+        assert_eq!(DeviceModel::Scph1200, fp.model);
+        assert!(fp.quirks.no_pressures);
+    }
+
+    #[test]
+    fn identifies_guitar() {
+        let fp = identify_device(&signature(0x01, NO_ACTUATORS));
+        assert_eq!(DeviceModel::GuitarHero, fp.model);
+        assert!(fp.quirks.no_pressures);
+    }
+
+    #[test]
+    fn identifies_wireless_receiver() {
+        let fp = identify_device(&signature(0x03, WIRELESS_ACTUATORS));
+        assert_eq!(DeviceModel::Wireless, fp.model);
+        assert!(fp.quirks.extra_sleep_ns > 0 && fp.quirks.no_pressures);
+        // the model byte of DualShock does not match
+        let fp = identify_device(&signature(0x01, WIRELESS_ACTUATORS));
+        assert_eq!(DeviceModel::Unknown, fp.model);
+    }
+
+    #[test]
+    fn identifies_by_id_without_config() {
+        let sig = |id| Signature {
+            id,
+            ..Default::default()
+        };
+        assert_eq!(DeviceModel::DancePad, identify_device(&sig(0x41)).model);
+        assert_eq!(DeviceModel::NeGcon, identify_device(&sig(0x23)).model);
+        assert_eq!(DeviceModel::Jogcon, identify_device(&sig(0xE3)).model);
+        assert!(identify_device(&sig(0x41)).quirks.no_config);
+    }
+}
@@ -1,10 +1,33 @@
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+pub mod analyzer;
+#[cfg(feature = "async")]
+pub mod asynch;
 mod buttons;
+mod capabilities;
 mod commands;
+mod connection;
 mod controller;
 mod device;
 mod driver;
+mod events;
+mod fingerprint;
+mod memcard;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod mouse;
+mod multitap;
+mod peripheral;
+pub mod recording;
+mod rumble;
+mod scheduler;
+mod snapshot;
+mod sticks;
+pub mod wire;
 
 /// PS2 Gamepad interface
 pub trait Gamepad {
@@ -12,21 +35,90 @@ pub trait Gamepad {
     fn is_active(&self) -> bool;
     fn is_changed(&self, btn: Ps2Button) -> bool;
     fn is_pressed(&self, btn: Ps2Button) -> bool;
-    fn is_pressed_all(&self, mask: u16) -> bool;
-    fn is_pressed_any(&self, mask: u16) -> bool;
+    fn is_pressed_all(&self, buttons: Ps2ButtonSet) -> bool;
+    fn is_pressed_any(&self, buttons: Ps2ButtonSet) -> bool;
     fn is_down(&self, btn: Ps2Button) -> bool;
     fn is_up(&self, btn: Ps2Button) -> bool;
-    fn pressed_buttons(&self, filter: u16) -> u16;
+    fn pressed_buttons(&self, filter: Ps2ButtonSet) -> Ps2ButtonSet;
     fn analog_sticks(&self) -> Ps2AnalogSticks;
+    fn processed_sticks(&self) -> Ps2ProcessedSticks;
+    fn pressure(&self, btn: Ps2Button) -> u8;
+    fn button_pressures(&self) -> Ps2ButtonPressures;
     fn whammy_bar(&self) -> u8;
 }
 
+/// Guitar Hero controller interface
+pub trait GuitarGamepad {
+    fn is_guitar(&self) -> bool;
+    fn is_guitar_pressed(&self, btn: Ps2GuitarButton) -> bool;
+    fn is_guitar_down(&self, btn: Ps2GuitarButton) -> bool;
+    fn frets(&self) -> u8;
+    fn strum(&self) -> Ps2Strum;
+    fn is_star_power(&self) -> bool;
+    fn whammy(&self) -> u8;
+}
+
+/// Pointing device interface, position is in units scaled by sensitivity
+pub trait Pointer {
+    fn position(&self) -> (i32, i32);
+    fn set_position(&mut self, x: i32, y: i32);
+    /// Movement counts of the last poll, Y is positive downward
+    fn delta(&self) -> (i8, i8);
+    fn is_pressed(&self, btn: Ps2MouseButton) -> bool;
+    fn is_down(&self, btn: Ps2MouseButton) -> bool;
+    fn is_up(&self, btn: Ps2MouseButton) -> bool;
+}
+
 pub mod prelude {
-    pub use super::buttons::{AnalogSticks as Ps2AnalogSticks, Button as Ps2Button};
-    pub use super::commands::{DeviceState as Ps2DeviceState, DeviceType as Ps2DeviceType};
-    pub use super::controller::create_psx_controller;
+    pub use super::buttons::{
+        AnalogSticks as Ps2AnalogSticks, Button as Ps2Button,
+        ButtonPressures as Ps2ButtonPressures, ButtonSet as Ps2ButtonSet,
+        ButtonSetIter as Ps2ButtonSetIter, GuitarButton as Ps2GuitarButton, Strum as Ps2Strum,
+    };
+    pub use super::capabilities::{
+        Actuator as Ps2Actuator, ActuatorType as Ps2ActuatorType,
+        DeviceCapabilities as Ps2DeviceCapabilities,
+    };
+    pub use super::commands::{
+        ControllerError as Ps2ControllerError, DeviceError as Ps2DeviceError,
+        DeviceMode as Ps2DeviceMode, DeviceState as Ps2DeviceState, DeviceType as Ps2DeviceType,
+    };
+    pub use super::connection::{
+        ConnectionConfig as Ps2ConnectionConfig, StateChange as Ps2StateChange,
+    };
     pub use super::controller::Controller as Ps2Controller;
+    pub use super::controller::{
+        create_psx_controller, create_psx_controller_with_ack, create_spi_controller,
+    };
+    pub use super::device::TimingProfile as Ps2TimingProfile;
+    pub use super::events::{ButtonEvent as Ps2ButtonEvent, EventConfig as Ps2EventConfig};
+    pub use super::fingerprint::identify_device;
+    pub use super::fingerprint::{
+        DeviceModel as Ps2DeviceModel, Fingerprint as Ps2Fingerprint, Quirks as Ps2Quirks,
+        Signature as Ps2Signature, FINGERPRINTS as PS2_FINGERPRINTS,
+    };
+    pub use super::memcard::{create_psx_memory_card, create_spi_memory_card};
+    pub use super::memcard::{
+        BlockState as Ps2BlockState, CardStatus as Ps2CardStatus, DirEntry as Ps2DirEntry,
+        MemoryCard as Ps2MemoryCard, MemoryCardError as Ps2MemoryCardError,
+    };
+    pub use super::mouse::{create_psx_mouse, create_spi_mouse};
+    pub use super::mouse::{MouseButton as Ps2MouseButton, PsxMouse as Ps2Mouse};
+    pub use super::multitap::{create_psx_multitap, create_spi_multitap};
+    pub use super::multitap::{Multitap as Ps2Multitap, TapPad as Ps2TapPad};
+    pub use super::peripheral::create_loopback_controller;
+    pub use super::peripheral::{Loopback as Ps2Loopback, PsxPeripheral as Ps2Peripheral};
+    pub use super::recording::create_replay_controller;
+    pub use super::rumble::Motors as Ps2Motors;
+    pub use super::scheduler::PollScheduler as Ps2PollScheduler;
+    pub use super::snapshot::GamepadSnapshot as Ps2GamepadSnapshot;
+    pub use super::sticks::{
+        ProcessedSticks as Ps2ProcessedSticks, ResponseCurve as Ps2ResponseCurve,
+        StickProcessor as Ps2StickProcessor,
+    };
     pub use super::Gamepad as Ps2Gamepad;
+    pub use super::GuitarGamepad as Ps2GuitarGamepad;
+    pub use super::Pointer as Ps2Pointer;
 }
 
 pub use self::prelude::*;
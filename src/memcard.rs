@@ -0,0 +1,408 @@
+use crate::commands::ErrorType;
+use crate::device::{PsxDevice, PsxTrasferData, SpiPsxDevice, TimingProfile};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+/// Creating an instance [`MemoryCard`] to work with a card connected to ordinary digital pins
+pub fn create_psx_memory_card<Dat, Cmd, Att, Clk, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    delay: D,
+) -> MemoryCard<PsxDevice<Dat, Cmd, Att, Clk, D>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    D: DelayNs,
+{
+    MemoryCard::new(PsxDevice::new(
+        dat,
+        cmd,
+        att,
+        clk,
+        delay,
+        TimingProfile::default(),
+    ))
+}
+
+/// Creating an instance [`MemoryCard`] to work with a card connected to the SPI peripheral
+pub fn create_spi_memory_card<Spi, Att, D>(
+    spi: Spi,
+    att: Att,
+    delay: D,
+) -> MemoryCard<SpiPsxDevice<Spi, Att, D>>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
+    D: DelayNs,
+{
+    MemoryCard::new(SpiPsxDevice::new(spi, att, delay))
+}
+
+/// Size of the frame (sector) in bytes
+pub const FRAME_SIZE: usize = 128;
+/// Number of frames of the standard 128 KB card
+pub const FRAME_COUNT: u16 = 1024;
+/// Number of directory entries, one per data block
+pub const DIR_ENTRIES: usize = 15;
+
+/// Address of the memory card on the bus
+const CARD_ADDRESS: u8 = 0x81;
+const CMD_READ: u8 = b'R';
+const CMD_WRITE: u8 = b'W';
+const CMD_GET_ID: u8 = b'S';
+const CARD_ID: [u8; 2] = [0x5A, 0x5D];
+const CMD_ACK: [u8; 2] = [0x5C, 0x5D];
+const END_GOOD: u8 = b'G';
+const END_BAD_CHECKSUM: u8 = b'N';
+/// FLAG bit, set after power on until the first write
+const FLAG_FRESH: u8 = 0x08;
+/// ATT high between frames, cards need no pause of the controller config mode
+const FRAME_GAP_NS: u32 = 20_000;
+
+/// Errors of memory card communication, `E` is the error of transport
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MemoryCardError<E> {
+    /// Card does not answer with its ID
+    NoCard,
+    /// Command acknowledge bytes are wrong
+    BadAck,
+    /// Checksum of the frame does not match
+    BadChecksum,
+    /// Frame number is rejected by card
+    BadSector,
+    /// GPIO or SPI failure
+    Transport(E),
+}
+
+type MResult<T, E> = Result<T, MemoryCardError<E>>;
+
+/// Status of the card, answered by Get ID command
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CardStatus {
+    /// FLAG byte of the last command
+    pub flag: u8,
+    /// Number of frames
+    pub frames: u16,
+    /// Size of frame in bytes
+    pub frame_size: u16,
+}
+impl CardStatus {
+    /// Card was not written since it was inserted
+    pub fn is_fresh(&self) -> bool {
+        (self.flag & FLAG_FRESH) > 0
+    }
+}
+
+/// Allocation state of the block from directory entry
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockState {
+    Free,
+    /// First block of the file
+    First,
+    Middle,
+    Last,
+    /// Block of the deleted file, first, middle or last
+    Deleted(u8),
+    Unknown(u32),
+}
+impl From<u32> for BlockState {
+    fn from(value: u32) -> Self {
+        match value {
+            0x51 => Self::First,
+            0x52 => Self::Middle,
+            0x53 => Self::Last,
+            0xA0 => Self::Free,
+            0xA1..=0xA3 => Self::Deleted(value as u8),
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+/// Directory entry of the data block
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DirEntry {
+    pub state: BlockState,
+    /// File size in bytes, in the first block only
+    pub size: u32,
+    /// Index of the next block of file, `None` for the last block
+    pub next: Option<u16>,
+    /// File name, ASCII padded by zeroes
+    pub name: [u8; 21],
+    /// Checksum of the entry is correct
+    pub is_valid: bool,
+}
+impl DirEntry {
+    /// Decode directory frame
+    pub fn from_frame(frame: &[u8; FRAME_SIZE]) -> Self {
+        let word =
+            |i: usize| u32::from_le_bytes([frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]);
+        let next = match u16::from_le_bytes([frame[8], frame[9]]) {
+            0xFFFF => None,
+            n => Some(n),
+        };
+        let mut name = [0u8; 21];
+        name.copy_from_slice(&frame[0x0A..0x1F]);
+        let checksum = frame[..FRAME_SIZE - 1].iter().fold(0, |acc, b| acc ^ b);
+        Self {
+            state: word(0).into(),
+            size: word(4),
+            next,
+            name,
+            is_valid: checksum == frame[FRAME_SIZE - 1],
+        }
+    }
+    /// File name up to the first zero, empty if it is not ASCII
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|b| 0 == *b)
+            .unwrap_or(self.name.len());
+        match self.name[..len].is_ascii() {
+            true => core::str::from_utf8(&self.name[..len]).unwrap_or(""),
+            false => "",
+        }
+    }
+    pub fn is_used(&self) -> bool {
+        matches!(
+            self.state,
+            BlockState::First | BlockState::Middle | BlockState::Last
+        )
+    }
+}
+
+/// Driver of the PS1/PS2 memory card (PS1 protocol), shares the bus with controller
+pub struct MemoryCard<Dev> {
+    dev: Dev,
+    flag: u8,
+}
+
+impl<Dev: PsxTrasferData> MemoryCard<Dev> {
+    pub fn new(dev: Dev) -> Self {
+        Self { dev, flag: 0 }
+    }
+    /// Release the transport
+    pub fn release(self) -> Dev {
+        self.dev
+    }
+    /// FLAG byte of the last command
+    pub fn flag(&self) -> u8 {
+        self.flag
+    }
+    /// Read card status and size
+    pub fn status(&mut self) -> MResult<CardStatus, Dev::Error> {
+        self.transaction(CMD_GET_ID, |me| {
+            me.expect(CMD_ACK, MemoryCardError::BadAck)?;
+            let mut info = [0u8; 4];
+            for byte in info.iter_mut() {
+                *byte = me.tx_rx(0x00)?;
+            }
+            Ok(CardStatus {
+                flag: me.flag,
+                frames: u16::from_be_bytes([info[0], info[1]]),
+                frame_size: u16::from_be_bytes([info[2], info[3]]),
+            })
+        })
+    }
+    /// Read frame 0 - 1023
+    pub fn read_frame(
+        &mut self,
+        frame: u16,
+        data: &mut [u8; FRAME_SIZE],
+    ) -> MResult<(), Dev::Error> {
+        if frame >= FRAME_COUNT {
+            return Err(MemoryCardError::BadSector);
+        }
+        let [msb, lsb] = frame.to_be_bytes();
+        self.transaction(CMD_READ, |me| {
+            me.tx_rx(msb)?;
+            me.tx_rx(lsb)?;
+            me.expect(CMD_ACK, MemoryCardError::BadAck)?;
+            // card confirms the address, 0xFFFF for wrong frame
+            me.expect([msb, lsb], MemoryCardError::BadSector)?;
+            let mut checksum = msb ^ lsb;
+            for byte in data.iter_mut() {
+                *byte = me.tx_rx(0x00)?;
+                checksum ^= *byte;
+            }
+            if checksum != me.tx_rx(0x00)? {
+                return Err(MemoryCardError::BadChecksum);
+            }
+            match me.tx_rx(0x00)? {
+                END_GOOD => Ok(()),
+                _ => Err(MemoryCardError::BadSector),
+            }
+        })
+    }
+    /// Write frame 0 - 1023
+    pub fn write_frame(&mut self, frame: u16, data: &[u8; FRAME_SIZE]) -> MResult<(), Dev::Error> {
+        if frame >= FRAME_COUNT {
+            return Err(MemoryCardError::BadSector);
+        }
+        let [msb, lsb] = frame.to_be_bytes();
+        self.transaction(CMD_WRITE, |me| {
+            me.tx_rx(msb)?;
+            me.tx_rx(lsb)?;
+            let mut checksum = msb ^ lsb;
+            for byte in data {
+                me.tx_rx(*byte)?;
+                checksum ^= *byte;
+            }
+            me.tx_rx(checksum)?;
+            me.expect(CMD_ACK, MemoryCardError::BadAck)?;
+            match me.tx_rx(0x00)? {
+                END_GOOD => Ok(()),
+                END_BAD_CHECKSUM => Err(MemoryCardError::BadChecksum),
+                _ => Err(MemoryCardError::BadSector),
+            }
+        })
+    }
+    /// Read directory entries of blocks 1 - 15, frames 1 - 15 of the first block
+    pub fn read_directory(&mut self) -> MResult<[DirEntry; DIR_ENTRIES], Dev::Error> {
+        let mut frame = [0u8; FRAME_SIZE];
+        let mut entries = [DirEntry::from_frame(&frame); DIR_ENTRIES];
+        for (i, entry) in entries.iter_mut().enumerate() {
+            self.read_frame(i as u16 + 1, &mut frame)?;
+            *entry = DirEntry::from_frame(&frame);
+        }
+        Ok(entries)
+    }
+
+    /// Address card, send command and check card ID, closure sends the rest of command
+    fn transaction<F, T>(&mut self, cmd: u8, f_cmd: F) -> MResult<T, Dev::Error>
+    where
+        F: FnOnce(&mut Self) -> MResult<T, Dev::Error>,
+    {
+        self.dev.start_cmd().map_err(MemoryCardError::Transport)?;
+        let result = self.send_header(cmd).and_then(|_| f_cmd(self));
+        self.dev.stop_cmd().map_err(MemoryCardError::Transport)?;
+        self.dev.delay_ns(FRAME_GAP_NS);
+        result
+    }
+    fn send_header(&mut self, cmd: u8) -> MResult<(), Dev::Error> {
+        self.tx_rx(CARD_ADDRESS)?;
+        if self.dev.last_ack() == Some(false) {
+            return Err(MemoryCardError::NoCard);
+        }
+        self.flag = self.tx_rx(cmd)?;
+        self.expect(CARD_ID, MemoryCardError::NoCard)
+    }
+    /// Receive two bytes, error if they are not equal to `expected`
+    fn expect(
+        &mut self,
+        expected: [u8; 2],
+        err: MemoryCardError<Dev::Error>,
+    ) -> MResult<(), Dev::Error> {
+        for byte in expected {
+            if byte != self.tx_rx(0x00)? {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+    fn tx_rx(&mut self, byte: u8) -> MResult<u8, Dev::Error> {
+        self.dev.send_8bit(byte).map_err(MemoryCardError::Transport)
+    }
+}
+
+impl<Dev: ErrorType> ErrorType for MemoryCard<Dev> {
+    type Error = Dev::Error;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{create_mock_memory_card, MockCard};
+
+    fn pattern(seed: u8) -> [u8; FRAME_SIZE] {
+        core::array::from_fn(|i| seed.wrapping_add(i as u8))
+    }
+
+    #[test]
+    fn status() {
+        let card = MockCard::new();
+        let mut mc = create_mock_memory_card(&card);
+        let status = mc.status().unwrap();
+        assert_eq!(FRAME_COUNT, status.frames);
+        assert_eq!(FRAME_SIZE as u16, status.frame_size);
+        assert!(status.is_fresh());
+
+        card.set_connected(false);
+        assert_eq!(Err(MemoryCardError::NoCard), mc.status());
+    }
+
+    #[test]
+    fn read_frame() {
+        let card = MockCard::new();
+        card.set_frame(0x3FF, &pattern(7));
+        let mut mc = create_mock_memory_card(&card);
+        let mut data = [0u8; FRAME_SIZE];
+        mc.read_frame(0x3FF, &mut data).unwrap();
+        assert_eq!(pattern(7), data);
+
+        assert_eq!(
+            Err(MemoryCardError::BadSector),
+            mc.read_frame(FRAME_COUNT, &mut data)
+        );
+        card.set_bad_frame(Some(5));
+        assert_eq!(Err(MemoryCardError::BadSector), mc.read_frame(5, &mut data));
+        card.add_noise();
+        assert_eq!(
+            Err(MemoryCardError::BadChecksum),
+            mc.read_frame(0x3FF, &mut data)
+        );
+    }
+
+    #[test]
+    fn write_frame() {
+        let card = MockCard::new();
+        let mut mc = create_mock_memory_card(&card);
+        mc.write_frame(0x100, &pattern(1)).unwrap();
+        assert_eq!(pattern(1), card.frame(0x100));
+        assert!(!mc.status().unwrap().is_fresh());
+
+        // card answers 'N' and keeps the frame
+        card.add_noise();
+        assert_eq!(
+            Err(MemoryCardError::BadChecksum),
+            mc.write_frame(0x100, &pattern(2))
+        );
+        assert_eq!(pattern(1), card.frame(0x100));
+        // end status of the rejected address
+        card.set_bad_frame(Some(0x100));
+        assert_eq!(
+            Err(MemoryCardError::BadSector),
+            mc.write_frame(0x100, &pattern(2))
+        );
+        assert_eq!(1, card.writes());
+    }
+
+    #[test]
+    fn read_directory() {
+        let card = MockCard::formatted();
+        let mut entry = [0u8; FRAME_SIZE];
+        entry[0] = 0x51;
+        entry[4..8].copy_from_slice(&8192u32.to_le_bytes());
+        entry[8..10].copy_from_slice(&[0xFF, 0xFF]);
+        entry[0x0A..0x16].copy_from_slice(b"BASLUS-00000");
+        entry[FRAME_SIZE - 1] = entry.iter().fold(0, |acc, b| acc ^ b);
+        card.set_frame(3, &entry);
+
+        let mut mc = create_mock_memory_card(&card);
+        let dir = mc.read_directory().unwrap();
+        assert!(dir.iter().all(|e| e.is_valid));
+        assert_eq!(BlockState::Free, dir[0].state);
+        assert!(!dir[0].is_used());
+        let file = &dir[2];
+        assert_eq!(BlockState::First, file.state);
+        assert_eq!((8192, None), (file.size, file.next));
+        assert_eq!("BASLUS-00000", file.name());
+    }
+}
@@ -0,0 +1,779 @@
+//! Emulated DualShock / DualShock 2 device for host-side testing,
+//! enabled by the `mock` feature.
+//!
+//! [`MockPad`] answers the bus like a real pad, the test scripts buttons and sticks
+//! between polls while the controller owns a [`MockDevice`] borrowing the pad.
+use crate::buttons::{AnalogSticks, Button, ButtonPressures};
+use crate::commands::*;
+use crate::controller::Controller;
+use crate::device::{ClockControl, PsxTrasferData, TimingProfile};
+use crate::driver::PsxDriver;
+use crate::memcard::{MemoryCard, FRAME_COUNT, FRAME_SIZE};
+use crate::mouse::{MouseButton, PsxMouse};
+use crate::multitap::{Multitap, TAP_SLOTS};
+use crate::peripheral::{PadProfile, PadState};
+use core::cell::RefCell;
+use embedded_hal::delay::DelayNs;
+
+/// Creating an instance [`Controller`] to work with the emulated device
+pub fn create_mock_controller(pad: &MockPad) -> Controller<PsxDriver<MockDevice<'_>>> {
+    Controller::new(PsxDriver::new(pad.device()))
+}
+
+/// Creating an instance [`MemoryCard`] to work with the emulated card
+pub fn create_mock_memory_card(card: &MockCard) -> MemoryCard<MockCardDevice<'_>> {
+    MemoryCard::new(card.device())
+}
+
+/// Creating an instance [`Multitap`] to work with the emulated multitap
+pub fn create_mock_multitap<'a>(
+    pads: [Option<&'a MockPad>; TAP_SLOTS],
+) -> Multitap<MockMultitap<'a>> {
+    Multitap::new(MockMultitap::new(pads))
+}
+
+/// Creating an instance [`PsxMouse`] to work with the emulated mouse
+pub fn create_mock_mouse() -> PsxMouse<MockMouse> {
+    PsxMouse::new(MockMouse::new())
+}
+
+/// Emulated model of device
+#[derive(Copy, Clone, PartialEq)]
+pub enum MockModel {
+    /// SCPH-1200, no pressures
+    DualShock,
+    /// SCPH-10010
+    DualShock2,
+    /// Guitar Hero guitar, model of DualShock without motors, it holds Left down
+    GuitarHero,
+    /// Digital pad without config mode
+    DancePad,
+    /// neGcon, ID 0x23 and no config mode
+    NeGcon,
+    /// Receiver of a wireless pad, model of DualShock 2 without pressures,
+    /// motors report no current
+    Wireless,
+}
+
+impl MockModel {
+    fn profile(&self) -> PadProfile {
+        let sony = |model: DeviceType| PadProfile {
+            model: model as u8,
+            is_pressures: false,
+            ..PadProfile::DUALSHOCK2
+        };
+        let no_config = PadProfile {
+            model: 0x00,
+            is_config: false,
+            ..sony(DeviceType::Unknown)
+        };
+        match self {
+            Self::DualShock => sony(DeviceType::DualShock1),
+            Self::DualShock2 => PadProfile::DUALSHOCK2,
+            Self::GuitarHero => PadProfile {
+                held: Button::Left as u16,
+                actuators: [[0x00; 6]; 2],
+                ..sony(DeviceType::DualShock1)
+            },
+            Self::DancePad => no_config,
+            Self::NeGcon => PadProfile {
+                fixed_id: Some(0x23),
+                ..no_config
+            },
+            Self::Wireless => PadProfile {
+                actuators: [
+                    [0x00, 0x00, 0x01, 0x02, 0x00, 0x00],
+                    [0x00, 0x00, 0x01, 0x01, 0x01, 0x00],
+                ],
+                ..sony(DeviceType::DualShock2)
+            },
+        }
+    }
+}
+
+/// Pad state machine with the scripted faults of the bus
+struct MockState {
+    pad: PadState,
+    is_connected: bool,
+    packets: u32,
+    elapsed_ns: u64,
+    fault: Option<DeviceError>,
+    timing: TimingProfile,
+    max_clock_hz: u32,
+    is_ack_line: bool,
+}
+
+impl MockState {
+    fn new(model: MockModel) -> Self {
+        Self {
+            pad: PadState::new(model.profile()),
+            is_connected: true,
+            packets: 0,
+            elapsed_ns: 0,
+            fault: None,
+            timing: TimingProfile::STANDARD,
+            max_clock_hz: u32::MAX,
+            is_ack_line: false,
+        }
+    }
+    fn check_fault(&self) -> Result<(), DeviceError> {
+        match self.fault {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+    fn is_too_fast(&self) -> bool {
+        self.timing.clock_hz() > self.max_clock_hz
+    }
+    /// Absent or too slow device does not drive DAT
+    fn is_answering(&self) -> bool {
+        self.is_connected && !self.is_too_fast()
+    }
+    /// Start of packet, device is selected by ATT
+    fn begin(&mut self) {
+        self.pad.select();
+    }
+    /// Apply command when packet is finished
+    fn execute(&mut self) {
+        if self.is_answering() && self.pad.is_packet() {
+            self.packets += 1;
+        }
+        self.pad.deselect();
+    }
+    fn exchange(&mut self, byte: u8) -> u8 {
+        match self.is_answering() {
+            true => self.pad.exchange(byte),
+            false => 0xFF,
+        }
+    }
+    /// ACK pulse after the last byte, every byte except the last of the packet
+    fn is_acked(&self) -> bool {
+        self.is_answering() && self.pad.is_acked()
+    }
+}
+
+/// Emulated device, scripted by tests
+pub struct MockPad {
+    state: RefCell<MockState>,
+}
+
+impl MockPad {
+    pub fn new(model: MockModel) -> Self {
+        Self {
+            state: RefCell::new(MockState::new(model)),
+        }
+    }
+    pub fn dualshock() -> Self {
+        Self::new(MockModel::DualShock)
+    }
+    pub fn dualshock2() -> Self {
+        Self::new(MockModel::DualShock2)
+    }
+    pub fn guitar() -> Self {
+        Self::new(MockModel::GuitarHero)
+    }
+    /// Transport connected to this pad
+    pub fn device(&self) -> MockDevice<'_> {
+        MockDevice { pad: self }
+    }
+    /// Plug or unplug device, unplugged device does not answer
+    /// and loses its settings
+    pub fn set_connected(&self, connected: bool) {
+        let mut st = self.state.borrow_mut();
+        st.is_connected = connected;
+        if !connected {
+            st.pad.power_off();
+        }
+    }
+    /// Simulate failure of transport, every exchange returns the error
+    pub fn set_fault(&self, fault: Option<DeviceError>) {
+        self.state.borrow_mut().fault = fault;
+    }
+    /// Fastest clock the pad answers at, like a wireless receiver
+    pub fn set_max_clock_hz(&self, clock_hz: u32) {
+        self.state.borrow_mut().max_clock_hz = clock_hz;
+    }
+    /// Clock set by the driver
+    pub fn timing(&self) -> TimingProfile {
+        self.state.borrow().timing
+    }
+    /// Report ACK of every byte to the transport, like a transport with ACK pin
+    pub fn set_ack_line(&self, enabled: bool) {
+        self.state.borrow_mut().is_ack_line = enabled;
+    }
+    /// Press button, pressure is 255
+    pub fn press(&self, btn: Button) {
+        self.set_pressure(btn, 0xFF);
+    }
+    /// Release button
+    pub fn release(&self, btn: Button) {
+        self.set_pressure(btn, 0x00);
+    }
+    /// Release all buttons
+    pub fn release_all(&self) {
+        let mut st = self.state.borrow_mut();
+        st.pad.set_buttons(0xFFFF);
+        *st.pad.pressures_mut() = [0u8; 12];
+    }
+    /// Set pressure of button, button is pressed for any value above 0
+    pub fn set_pressure(&self, btn: Button, value: u8) {
+        let mut st = self.state.borrow_mut();
+        let full = ButtonPressures::from_buttons(btn.into());
+        for (p, v) in st.pad.pressures_mut().iter_mut().zip(full.as_bytes()) {
+            if 0 != *v {
+                *p = value;
+            }
+        }
+        let buttons = match value {
+            0 => st.pad.buttons() | btn as u16,
+            _ => st.pad.buttons() & !(btn as u16),
+        };
+        st.pad.set_buttons(buttons);
+    }
+    pub fn set_sticks(&self, sticks: AnalogSticks) {
+        self.state.borrow_mut().pad.set_sticks(sticks);
+    }
+    /// Press the Analog button of device, ignored if mode is locked
+    pub fn press_analog_button(&self) {
+        self.state.borrow_mut().pad.press_analog_button();
+    }
+    pub fn is_analog(&self) -> bool {
+        self.state.borrow().pad.is_analog()
+    }
+    pub fn is_locked(&self) -> bool {
+        self.state.borrow().pad.is_locked()
+    }
+    pub fn is_config(&self) -> bool {
+        self.state.borrow().pad.is_config()
+    }
+    pub fn is_pressures(&self) -> bool {
+        self.state.borrow().pad.is_pressures()
+    }
+    /// Last values received for small and large motors
+    pub fn motors(&self) -> (u8, u8) {
+        self.state.borrow().pad.motors()
+    }
+    /// Number of complete packets addressed to device
+    pub fn packets(&self) -> u32 {
+        self.state.borrow().packets
+    }
+    /// Total time of all delays requested by driver
+    pub fn elapsed_ns(&self) -> u64 {
+        self.state.borrow().elapsed_ns
+    }
+}
+
+/// Transport of emulated device
+pub struct MockDevice<'a> {
+    pad: &'a MockPad,
+}
+
+impl ErrorType for MockDevice<'_> {
+    type Error = DeviceError;
+}
+
+impl DelayNs for MockDevice<'_> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.pad.state.borrow_mut().elapsed_ns += ns as u64;
+    }
+}
+
+impl ClockControl for MockDevice<'_> {
+    fn timing(&self) -> TimingProfile {
+        self.pad.state.borrow().timing
+    }
+    fn set_timing(&mut self, timing: TimingProfile) {
+        self.pad.state.borrow_mut().timing = timing;
+    }
+}
+
+impl PsxTrasferData for MockDevice<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        let mut st = self.pad.state.borrow_mut();
+        st.begin();
+        st.check_fault()
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        let mut st = self.pad.state.borrow_mut();
+        st.execute();
+        st.check_fault()
+    }
+
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        let mut st = self.pad.state.borrow_mut();
+        st.check_fault()?;
+        Ok(st.exchange(byte))
+    }
+
+    fn last_ack(&self) -> Option<bool> {
+        let st = self.pad.state.borrow();
+        st.is_ack_line.then(|| st.is_acked())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for MockDevice<'_> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.pad.state.borrow_mut().elapsed_ns += ns as u64;
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::asynch::PsxTrasferData for MockDevice<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    async fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        PsxTrasferData::start_cmd(self)
+    }
+
+    async fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        PsxTrasferData::stop_cmd(self)
+    }
+
+    async fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        PsxTrasferData::send_8bit(self, byte)
+    }
+
+    fn last_ack(&self) -> Option<bool> {
+        PsxTrasferData::last_ack(self)
+    }
+}
+
+/// Scripted content and protocol state machine of emulated memory card
+struct CardState {
+    frames: [[u8; FRAME_SIZE]; FRAME_COUNT as usize],
+    is_connected: bool,
+    flag: u8,
+    cursor: usize,
+    is_selected: bool,
+    cmd: u8,
+    prev: u8,
+    frame: u16,
+    checksum: u8,
+    data: [u8; FRAME_SIZE],
+    writes: u32,
+    bad_frame: Option<u16>,
+    is_noise: bool,
+}
+
+impl CardState {
+    fn new() -> Self {
+        Self {
+            frames: [[0u8; FRAME_SIZE]; FRAME_COUNT as usize],
+            is_connected: true,
+            flag: 0x08,
+            cursor: 0,
+            is_selected: false,
+            cmd: 0,
+            prev: 0,
+            frame: 0,
+            checksum: 0,
+            data: [0u8; FRAME_SIZE],
+            writes: 0,
+            bad_frame: None,
+            is_noise: false,
+        }
+    }
+    fn is_valid_frame(&self) -> bool {
+        self.frame < FRAME_COUNT && Some(self.frame) != self.bad_frame
+    }
+    /// Bit flipped in the first data byte of the frame
+    fn noise(&mut self) -> u8 {
+        match core::mem::take(&mut self.is_noise) {
+            true => 0x01,
+            false => 0x00,
+        }
+    }
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let n = self.cursor;
+        self.cursor += 1;
+        if !self.is_connected {
+            return 0xFF;
+        }
+        let answer = match n {
+            0 => {
+                self.is_selected = 0x81 == byte;
+                0xFF
+            }
+            _ if !self.is_selected => 0xFF,
+            1 => {
+                self.cmd = byte;
+                self.flag
+            }
+            2 => 0x5A,
+            3 => 0x5D,
+            _ => match self.cmd {
+                b'R' => self.read(n, byte),
+                b'W' => self.write(n, byte),
+                b'S' => [0x5C, 0x5D, 0x04, 0x00, 0x00, 0x80]
+                    .get(n - 4)
+                    .copied()
+                    .unwrap_or(0xFF),
+                _ => 0xFF,
+            },
+        };
+        self.prev = byte;
+        answer
+    }
+    fn receive_address(&mut self, n: usize, byte: u8) -> u8 {
+        match n {
+            4 => {
+                self.frame = (byte as u16) << 8;
+                self.checksum = byte;
+                0x00
+            }
+            _ => {
+                self.frame |= byte as u16;
+                self.checksum ^= byte;
+                self.prev
+            }
+        }
+    }
+    fn read(&mut self, n: usize, byte: u8) -> u8 {
+        const DATA: usize = 10;
+        let [msb, lsb] = match self.is_valid_frame() {
+            true => self.frame.to_be_bytes(),
+            false => [0xFF, 0xFF],
+        };
+        match n {
+            4 | 5 => self.receive_address(n, byte),
+            6 => 0x5C,
+            7 => 0x5D,
+            8 => msb,
+            9 => lsb,
+            _ if !self.is_valid_frame() => 0xFF,
+            DATA => self.frames[self.frame as usize][0] ^ self.noise(),
+            _ if n < DATA + FRAME_SIZE => self.frames[self.frame as usize][n - DATA],
+            _ if n == DATA + FRAME_SIZE => {
+                let data = &self.frames[self.frame as usize];
+                data.iter().fold(self.checksum, |acc, b| acc ^ b)
+            }
+            _ if n == DATA + FRAME_SIZE + 1 => b'G',
+            _ => 0xFF,
+        }
+    }
+    fn write(&mut self, n: usize, byte: u8) -> u8 {
+        const DATA: usize = 6;
+        match n {
+            4 | 5 => self.receive_address(n, byte),
+            _ if n < DATA + FRAME_SIZE => {
+                let byte = match n {
+                    DATA => byte ^ self.noise(),
+                    _ => byte,
+                };
+                self.data[n - DATA] = byte;
+                self.checksum ^= byte;
+                self.prev
+            }
+            _ if n == DATA + FRAME_SIZE => {
+                self.checksum ^= byte;
+                self.prev
+            }
+            _ if n == DATA + FRAME_SIZE + 1 => 0x5C,
+            _ if n == DATA + FRAME_SIZE + 2 => 0x5D,
+            _ if n == DATA + FRAME_SIZE + 3 => match (self.checksum, self.is_valid_frame()) {
+                (0, true) => {
+                    self.frames[self.frame as usize] = self.data;
+                    self.flag &= !0x08;
+                    self.writes += 1;
+                    b'G'
+                }
+                (_, true) => b'N',
+                (_, false) => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+}
+
+/// Emulated memory card, scripted by tests
+pub struct MockCard {
+    state: RefCell<CardState>,
+}
+
+impl Default for MockCard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockCard {
+    /// Card filled by zeroes
+    pub fn new() -> Self {
+        Self {
+            state: RefCell::new(CardState::new()),
+        }
+    }
+    /// Card with the header frame and the empty directory
+    pub fn formatted() -> Self {
+        let card = Self::new();
+        let mut header = [0u8; FRAME_SIZE];
+        header[..2].copy_from_slice(b"MC");
+        header[FRAME_SIZE - 1] = b'M' ^ b'C';
+        card.set_frame(0, &header);
+        for i in 1..16 {
+            let mut entry = [0u8; FRAME_SIZE];
+            entry[0] = 0xA0;
+            entry[8..10].copy_from_slice(&[0xFF, 0xFF]);
+            entry[FRAME_SIZE - 1] = entry.iter().fold(0, |acc, b| acc ^ b);
+            card.set_frame(i, &entry);
+        }
+        card
+    }
+    /// Transport connected to this card
+    pub fn device(&self) -> MockCardDevice<'_> {
+        MockCardDevice { card: self }
+    }
+    /// Insert or remove card, removed card does not answer
+    pub fn set_connected(&self, connected: bool) {
+        let mut st = self.state.borrow_mut();
+        st.is_connected = connected;
+        st.flag = 0x08;
+    }
+    pub fn set_frame(&self, frame: u16, data: &[u8; FRAME_SIZE]) {
+        self.state.borrow_mut().frames[frame as usize] = *data;
+    }
+    pub fn frame(&self, frame: u16) -> [u8; FRAME_SIZE] {
+        self.state.borrow().frames[frame as usize]
+    }
+    /// Number of successful writes
+    pub fn writes(&self) -> u32 {
+        self.state.borrow().writes
+    }
+    /// Worn out frame, the card rejects its address
+    pub fn set_bad_frame(&self, frame: Option<u16>) {
+        self.state.borrow_mut().bad_frame = frame;
+    }
+    /// Corrupt a bit of the next frame read or written
+    pub fn add_noise(&self) {
+        self.state.borrow_mut().is_noise = true;
+    }
+}
+
+/// Transport of emulated memory card
+pub struct MockCardDevice<'a> {
+    card: &'a MockCard,
+}
+
+impl ErrorType for MockCardDevice<'_> {
+    type Error = DeviceError;
+}
+
+impl DelayNs for MockCardDevice<'_> {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl PsxTrasferData for MockCardDevice<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        let mut st = self.card.state.borrow_mut();
+        st.cursor = 0;
+        st.is_selected = false;
+        Ok(())
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        Ok(())
+    }
+
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        Ok(self.card.state.borrow_mut().exchange(byte))
+    }
+}
+
+/// Emulated multitap with pads in slots, it is the transport itself.
+/// Like the real one, the TAP byte switches the mode of the next transaction,
+/// otherwise the packet is passed to slot A
+pub struct MockMultitap<'a> {
+    pads: [Option<&'a MockPad>; TAP_SLOTS],
+    is_connected: bool,
+    is_tap_mode: bool,
+    is_tap_next: bool,
+    cursor: usize,
+}
+
+impl<'a> MockMultitap<'a> {
+    pub fn new(pads: [Option<&'a MockPad>; TAP_SLOTS]) -> Self {
+        Self {
+            pads,
+            is_connected: true,
+            is_tap_mode: false,
+            is_tap_next: false,
+            cursor: 0,
+        }
+    }
+    /// Plug or unplug multitap, it loses the multitap mode
+    pub fn set_connected(&mut self, connected: bool) {
+        self.is_connected = connected;
+        self.is_tap_mode = false;
+    }
+    /// Slot 0 - 3 of the multitap data, header is 3 bytes
+    fn slot(n: usize) -> (usize, usize) {
+        ((n - 3) / 8, (n - 3) % 8)
+    }
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let n = self.cursor;
+        self.cursor += 1;
+        if !self.is_connected {
+            return 0xFF;
+        }
+        if 2 == n {
+            self.is_tap_next = 0x01 == byte;
+        }
+        if !self.is_tap_mode {
+            return match self.pads[0] {
+                Some(pad) => pad.state.borrow_mut().exchange(byte),
+                None => 0xFF,
+            };
+        }
+        match n {
+            0 => 0xFF,
+            1 => 0x80,
+            2 => 0x5A,
+            _ if n < 3 + 8 * TAP_SLOTS => {
+                let (slot, offset) = Self::slot(n);
+                let Some(pad) = self.pads[slot] else {
+                    return 0xFF;
+                };
+                let mut st = pad.state.borrow_mut();
+                if 0 == offset {
+                    st.begin();
+                    st.exchange(0x01);
+                }
+                let answer = st.exchange(byte);
+                if 7 == offset {
+                    st.execute();
+                }
+                answer
+            }
+            _ => 0xFF,
+        }
+    }
+}
+
+impl ErrorType for MockMultitap<'_> {
+    type Error = DeviceError;
+}
+
+impl DelayNs for MockMultitap<'_> {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl PsxTrasferData for MockMultitap<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.cursor = 0;
+        self.is_tap_next = false;
+        if let (false, Some(pad)) = (self.is_tap_mode, self.pads[0]) {
+            pad.state.borrow_mut().begin();
+        }
+        Ok(())
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        if let (false, Some(pad)) = (self.is_tap_mode, self.pads[0]) {
+            pad.state.borrow_mut().execute();
+        }
+        if self.is_connected && self.cursor > 2 {
+            self.is_tap_mode = self.is_tap_next;
+        }
+        Ok(())
+    }
+
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        Ok(self.exchange(byte))
+    }
+}
+
+/// Emulated mouse SCPH-1090, it is the transport itself.
+/// Movement is reported by the next poll and cleared
+pub struct MockMouse {
+    is_connected: bool,
+    buttons: u8,
+    delta: (i8, i8),
+    cursor: usize,
+}
+
+impl Default for MockMouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockMouse {
+    pub fn new() -> Self {
+        Self {
+            is_connected: true,
+            buttons: 0xFF,
+            delta: (0, 0),
+            cursor: 0,
+        }
+    }
+    /// Plug or unplug mouse, unplugged mouse does not answer
+    pub fn set_connected(&mut self, connected: bool) {
+        self.is_connected = connected;
+    }
+    pub fn press(&mut self, btn: MouseButton) {
+        self.buttons &= !(btn as u8);
+    }
+    pub fn release(&mut self, btn: MouseButton) {
+        self.buttons |= btn as u8;
+    }
+    /// Add movement reported by the next poll
+    pub fn move_by(&mut self, dx: i8, dy: i8) {
+        self.delta = (
+            self.delta.0.saturating_add(dx),
+            self.delta.1.saturating_add(dy),
+        );
+    }
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let n = self.cursor;
+        self.cursor += 1;
+        if !self.is_connected {
+            return 0xFF;
+        }
+        match (n, byte) {
+            (0, _) => 0xFF,
+            (1, 0x42) => 0x12,
+            (2, _) => 0x5A,
+            (3, _) => 0xFF,
+            (4, _) => self.buttons,
+            (5, _) => self.delta.0 as u8,
+            (6, _) => self.delta.1 as u8,
+            _ => 0xFF,
+        }
+    }
+}
+
+impl ErrorType for MockMouse {
+    type Error = DeviceError;
+}
+
+impl DelayNs for MockMouse {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl PsxTrasferData for MockMouse {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.cursor = 0;
+        Ok(())
+    }
+
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        if self.cursor > 6 {
+            self.delta = (0, 0);
+        }
+        Ok(())
+    }
+
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        Ok(self.exchange(byte))
+    }
+}
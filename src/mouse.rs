@@ -0,0 +1,356 @@
+use crate::{
+    commands::{CResult, ControllerError, DeviceState, ErrorType},
+    connection::{Connection, ConnectionConfig, StateChange},
+    controller::POLL_INTERVAL_MS,
+    device::{PsxDevice, PsxTrasferData, SpiPsxDevice, TimingProfile},
+    Pointer,
+};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+/// Creating an instance [`PsxMouse`] to work with a mouse connected to ordinary digital pins
+pub fn create_psx_mouse<Dat, Cmd, Att, Clk, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    delay: D,
+) -> PsxMouse<PsxDevice<Dat, Cmd, Att, Clk, D>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    D: DelayNs,
+{
+    PsxMouse::new(PsxDevice::new(
+        dat,
+        cmd,
+        att,
+        clk,
+        delay,
+        TimingProfile::default(),
+    ))
+}
+
+/// Creating an instance [`PsxMouse`] to work with a mouse connected to the SPI peripheral
+pub fn create_spi_mouse<Spi, Att, D>(
+    spi: Spi,
+    att: Att,
+    delay: D,
+) -> PsxMouse<SpiPsxDevice<Spi, Att, D>>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
+    D: DelayNs,
+{
+    PsxMouse::new(SpiPsxDevice::new(spi, att, delay))
+}
+
+/// ID answered by mouse: mode 0x1, 2 words
+const MOUSE_ID: u8 = 0x12;
+/// Bytes of the response: 0xFF, buttons, X and Y
+const REPORT_SIZE: usize = 4;
+/// Bits of buttons are active low, others are always set
+const NO_BUTTONS: u8 = 0xFF;
+/// Sensitivity of one count per unit of position
+const SENSITIVITY_ONE: u16 = 100;
+
+/// Buttons of mouse, bits of the second byte of the report
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MouseButton {
+    Right = 0x04,
+    Left = 0x08,
+}
+
+/// PlayStation mouse SCPH-1090, relative movement and two buttons.
+/// Position is accumulated from the movement scaled by sensitivity
+pub struct PsxMouse<Dev: ErrorType> {
+    pub state: DeviceState,
+    buttons: u8,
+    buttons_prev: u8,
+    delta: (i8, i8),
+    // position in hundredths, the fraction of scaled movement is kept
+    position: (i32, i32),
+    sensitivity: u16,
+    bounds: Option<(u16, u16)>,
+    poll_interval_ms: u32,
+    timestamp_ms: u32,
+    connection: Connection,
+    last_error: Option<ControllerError<Dev::Error>>,
+    dev: Dev,
+}
+
+impl<Dev: PsxTrasferData> PsxMouse<Dev> {
+    pub fn new(dev: Dev) -> Self {
+        Self {
+            state: DeviceState::Disconnected,
+            buttons: NO_BUTTONS,
+            buttons_prev: NO_BUTTONS,
+            delta: (0, 0),
+            position: (0, 0),
+            sensitivity: SENSITIVITY_ONE,
+            bounds: None,
+            poll_interval_ms: POLL_INTERVAL_MS,
+            timestamp_ms: 0,
+            connection: Connection::new(),
+            last_error: None,
+            dev,
+        }
+    }
+    /// Release the transport
+    pub fn release(self) -> Dev {
+        self.dev
+    }
+    /// Detect mouse, it has no config mode
+    pub fn connect(&mut self) {
+        self.set_state(DeviceState::Probing);
+        self.dev.sleep();
+        self.buttons_prev = self.buttons;
+        match self.transaction() {
+            Ok(report) => {
+                self.update(report);
+                self.connection.connected();
+                self.set_state(DeviceState::Connected);
+            }
+            Err(e) => {
+                self.last_error = Some(e);
+                self.connection.probe_failed(self.timestamp_ms);
+                self.set_state(DeviceState::Disconnected);
+            }
+        }
+    }
+    /// Polling movement and buttons, timestamp advances by poll interval
+    pub fn poll(&mut self) {
+        self.poll_at(self.timestamp_ms.wrapping_add(self.poll_interval_ms));
+    }
+    /// Polling at time `now_ms` of monotonic clock.
+    /// Lost mouse is probed again when the delay of backoff expires
+    pub fn poll_at(&mut self, now_ms: u32) {
+        self.timestamp_ms = now_ms;
+        match self.state {
+            DeviceState::Connected => self.poll_mouse(),
+            _ if self.connection.is_probe_due(now_ms) => self.connect(),
+            _ => {}
+        }
+    }
+    /// Set interval used by [`PsxMouse::poll`]
+    pub fn set_poll_interval(&mut self, interval_ms: u32) {
+        self.poll_interval_ms = interval_ms;
+    }
+    /// Set failures to lose mouse and delays between probes
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.connection.set_config(config);
+    }
+    /// Next change of connection state, changes are kept until read
+    pub fn next_state_change(&mut self) -> Option<StateChange> {
+        self.connection.pop()
+    }
+    /// Number of failed polls in a row
+    pub fn failures(&self) -> u8 {
+        self.connection.failures()
+    }
+    /// Movement scale in percent, 100 - one count of mouse per unit of position
+    pub fn set_sensitivity(&mut self, percent: u16) {
+        self.sensitivity = percent;
+    }
+    pub fn sensitivity(&self) -> u16 {
+        self.sensitivity
+    }
+    /// Keep position within `0..width` and `0..height`, for example a screen
+    pub fn set_bounds(&mut self, width: u16, height: u16) {
+        self.bounds = Some((width, height));
+        self.position = self.clamp(self.position);
+    }
+    /// Error of the last failed poll, cleared by a successful one
+    pub fn last_error(&self) -> Option<&ControllerError<Dev::Error>> {
+        self.last_error.as_ref()
+    }
+
+    /// Failed poll has no movement, buttons are released when mouse is lost
+    fn poll_mouse(&mut self) {
+        self.buttons_prev = self.buttons;
+        match self.transaction() {
+            Ok(report) => {
+                self.update(report);
+                self.connection.poll_ok();
+            }
+            Err(e) => {
+                self.delta = (0, 0);
+                self.last_error = Some(e);
+                if self.connection.poll_failed(self.timestamp_ms) {
+                    self.buttons = NO_BUTTONS;
+                    self.set_state(DeviceState::Lost);
+                }
+            }
+        }
+    }
+    fn update(&mut self, report: [u8; REPORT_SIZE]) {
+        self.buttons = report[1];
+        self.delta = (report[2] as i8, report[3] as i8);
+        self.apply_delta();
+        self.last_error = None;
+    }
+    /// Change state of connection and remember the change
+    fn set_state(&mut self, state: DeviceState) {
+        if self.state != state {
+            self.connection.push(StateChange {
+                from: self.state,
+                to: state,
+                at_ms: self.timestamp_ms,
+            });
+            self.state = state;
+        }
+    }
+    fn apply_delta(&mut self) {
+        let scale = self.sensitivity as i32;
+        let (x, y) = self.position;
+        let position = (
+            x.saturating_add(self.delta.0 as i32 * scale),
+            y.saturating_add(self.delta.1 as i32 * scale),
+        );
+        self.position = self.clamp(position);
+    }
+    /// Poll command, the report follows the header
+    fn transaction(&mut self) -> CResult<[u8; REPORT_SIZE], Dev::Error> {
+        self.dev.start_cmd().map_err(ControllerError::Transport)?;
+        let result = self.exchange_report();
+        self.dev.stop_cmd().map_err(ControllerError::Transport)?;
+        result
+    }
+    fn exchange_report(&mut self) -> CResult<[u8; REPORT_SIZE], Dev::Error> {
+        self.tx_rx(0x01)?;
+        let id = self.tx_rx(0x42)?;
+        let ack = self.tx_rx(0x00)?;
+        match (id, ack) {
+            (MOUSE_ID, 0x5A) => {}
+            (0xFF, _) => return Err(ControllerError::NoResponse),
+            _ => return Err(ControllerError::BadHeader),
+        }
+        let mut report = [0u8; REPORT_SIZE];
+        for rx in report.iter_mut() {
+            *rx = self.tx_rx(0x00)?;
+        }
+        Ok(report)
+    }
+    fn tx_rx(&mut self, byte: u8) -> CResult<u8, Dev::Error> {
+        self.dev.send_8bit(byte).map_err(ControllerError::Transport)
+    }
+}
+
+impl<Dev: ErrorType> PsxMouse<Dev> {
+    fn clamp(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        match self.bounds {
+            Some((w, h)) => {
+                let max = |size: u16| (size.max(1) as i32 - 1) * SENSITIVITY_ONE as i32;
+                (x.clamp(0, max(w)), y.clamp(0, max(h)))
+            }
+            None => (x, y),
+        }
+    }
+}
+
+impl<Dev: ErrorType> Pointer for PsxMouse<Dev> {
+    /// Whole units, a negative fraction rounds down
+    fn position(&self) -> (i32, i32) {
+        let unit = SENSITIVITY_ONE as i32;
+        (
+            self.position.0.div_euclid(unit),
+            self.position.1.div_euclid(unit),
+        )
+    }
+    /// Position is kept within bounds
+    fn set_position(&mut self, x: i32, y: i32) {
+        let unit = SENSITIVITY_ONE as i32;
+        self.position = self.clamp((x.saturating_mul(unit), y.saturating_mul(unit)));
+    }
+    fn delta(&self) -> (i8, i8) {
+        self.delta
+    }
+    fn is_pressed(&self, btn: MouseButton) -> bool {
+        (!self.buttons & btn as u8) > 0
+    }
+    fn is_down(&self, btn: MouseButton) -> bool {
+        self.is_pressed(btn) && (self.buttons_prev & btn as u8) > 0
+    }
+    fn is_up(&self, btn: MouseButton) -> bool {
+        !self.is_pressed(btn) && (!self.buttons_prev & btn as u8) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::create_mock_mouse;
+
+    #[test]
+    fn report() {
+        let mut mouse = create_mock_mouse();
+        mouse.poll_at(0);
+        assert_eq!(DeviceState::Connected, mouse.state);
+        mouse.dev.press(MouseButton::Left);
+        mouse.dev.move_by(5, -3);
+        mouse.poll();
+        assert!(mouse.is_down(MouseButton::Left) && !mouse.is_pressed(MouseButton::Right));
+        assert_eq!((5, -3), mouse.delta());
+        assert_eq!((5, -3), mouse.position());
+
+        mouse.dev.release(MouseButton::Left);
+        mouse.poll();
+        assert!(mouse.is_up(MouseButton::Left));
+        assert_eq!((0, 0), mouse.delta());
+
+        mouse.dev.set_connected(false);
+        mouse.poll();
+        assert_eq!(Some(&ControllerError::NoResponse), mouse.last_error());
+        assert_eq!(1, mouse.failures());
+    }
+
+    #[test]
+    fn sensitivity() {
+        let mut mouse = create_mock_mouse();
+        mouse.poll_at(0);
+        mouse.set_sensitivity(50);
+        // half counts are kept until they make a unit
+        mouse.dev.move_by(3, -3);
+        mouse.poll();
+        assert_eq!((1, -2), mouse.position());
+        mouse.dev.move_by(1, -1);
+        mouse.poll();
+        assert_eq!((2, -2), mouse.position());
+
+        mouse.set_sensitivity(250);
+        mouse.set_position(0, 0);
+        mouse.dev.move_by(-1, 2);
+        mouse.poll();
+        assert_eq!((-3, 5), mouse.position());
+    }
+
+    #[test]
+    fn bounds() {
+        let mut mouse = create_mock_mouse();
+        mouse.poll_at(0);
+        mouse.set_position(-10, 50);
+        mouse.set_bounds(320, 240);
+        assert_eq!((0, 50), mouse.position());
+        mouse.set_position(400, 300);
+        assert_eq!((319, 239), mouse.position());
+
+        mouse.dev.move_by(127, -128);
+        mouse.poll();
+        assert_eq!((319, 111), mouse.position());
+        mouse.dev.move_by(-128, -128);
+        mouse.poll();
+        mouse.dev.move_by(-128, 0);
+        mouse.poll();
+        mouse.dev.move_by(-128, 0);
+        mouse.poll();
+        assert_eq!((0, 0), mouse.position());
+    }
+}
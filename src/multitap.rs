@@ -0,0 +1,408 @@
+use crate::{
+    buttons::{AnalogSticks, Button, ButtonPressures, ButtonSet, ButtonState},
+    commands::{CResult, ControllerError, DeviceMode, DeviceState, ErrorType},
+    connection::{Connection, ConnectionConfig, StateChange},
+    controller::POLL_INTERVAL_MS,
+    device::{PsxDevice, PsxTrasferData, SpiPsxDevice, TimingProfile},
+    driver::DeviceBuffer,
+    sticks::{ProcessedSticks, StickProcessor},
+    Gamepad,
+};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+
+/// Creating an instance [`Multitap`] to work with a multitap connected to ordinary digital pins
+pub fn create_psx_multitap<Dat, Cmd, Att, Clk, D>(
+    dat: Dat,
+    cmd: Cmd,
+    att: Att,
+    clk: Clk,
+    delay: D,
+) -> Multitap<PsxDevice<Dat, Cmd, Att, Clk, D>>
+where
+    Dat: InputPin,
+    Cmd: OutputPin,
+    Att: OutputPin,
+    Clk: OutputPin,
+    D: DelayNs,
+{
+    Multitap::new(PsxDevice::new(
+        dat,
+        cmd,
+        att,
+        clk,
+        delay,
+        TimingProfile::default(),
+    ))
+}
+
+/// Creating an instance [`Multitap`] to work with a multitap connected to the SPI peripheral
+pub fn create_spi_multitap<Spi, Att, D>(
+    spi: Spi,
+    att: Att,
+    delay: D,
+) -> Multitap<SpiPsxDevice<Spi, Att, D>>
+where
+    Spi: SpiDevice,
+    Att: OutputPin,
+    D: DelayNs,
+{
+    Multitap::new(SpiPsxDevice::new(spi, att, delay))
+}
+
+/// Number of slots of the multitap
+pub const TAP_SLOTS: usize = 4;
+
+/// ID answered by multitap in the multitap mode
+const TAP_ID: u8 = 0x80;
+/// TAP byte of the header, requests all slots in the next transaction
+const TAP_ALL_SLOTS: u8 = 0x01;
+/// Bytes of one slot: ID, 0x5A, buttons and sticks
+const SLOT_SIZE: usize = 8;
+const SLOT_POLL: [u8; SLOT_SIZE] = [0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+/// State of the pad in one slot of the multitap
+#[derive(Copy, Clone)]
+pub struct TapPad {
+    pub state: DeviceState,
+    pub cmode: DeviceMode,
+    analog: AnalogSticks,
+    buttons: ButtonState,
+    stick_processor: StickProcessor,
+}
+
+impl Default for TapPad {
+    fn default() -> Self {
+        Self {
+            state: DeviceState::Disconnected,
+            cmode: DeviceMode::Unknown,
+            analog: AnalogSticks::default(),
+            buttons: ButtonState::RELEASED,
+            stick_processor: StickProcessor::default(),
+        }
+    }
+}
+
+impl TapPad {
+    /// Apply slot data, empty slot answers 0xFF
+    fn update(&mut self, data: &[u8; SLOT_SIZE]) {
+        let buf = DeviceBuffer::from_slot(data);
+        self.cmode = buf.rx_data_id().into();
+        if 0x5A != buf.data[2] || !matches!(self.cmode, DeviceMode::Digital | DeviceMode::Analog) {
+            self.state = DeviceState::Disconnected;
+            self.cmode = DeviceMode::Unknown;
+            self.buttons.release();
+            self.analog = AnalogSticks::default();
+            return;
+        }
+        self.state = DeviceState::Connected;
+        self.buttons.update(buf.rx_buttons());
+        self.analog = match buf.rx_is_analog_mode() {
+            true => buf.rx_analog_sticks(),
+            false => AnalogSticks::default(),
+        };
+    }
+    fn disconnect(&mut self) {
+        self.update(&[0xFF; SLOT_SIZE]);
+    }
+}
+
+/// Multitap SCPH-10090 polling four pads in one transaction.
+/// Slots report buttons and sticks only, pressures and vibration are not available
+pub struct Multitap<Dev: ErrorType> {
+    pub state: DeviceState,
+    pads: [TapPad; TAP_SLOTS],
+    poll_interval_ms: u32,
+    timestamp_ms: u32,
+    connection: Connection,
+    last_error: Option<ControllerError<Dev::Error>>,
+    dev: Dev,
+}
+
+impl<Dev: PsxTrasferData> Multitap<Dev> {
+    pub fn new(dev: Dev) -> Self {
+        Self {
+            state: DeviceState::Disconnected,
+            pads: [TapPad::default(); TAP_SLOTS],
+            poll_interval_ms: POLL_INTERVAL_MS,
+            timestamp_ms: 0,
+            connection: Connection::new(),
+            last_error: None,
+            dev,
+        }
+    }
+    /// Release the transport
+    pub fn release(self) -> Dev {
+        self.dev
+    }
+    /// Detect multitap, the first transaction switches it to the multitap mode
+    pub fn connect(&mut self) {
+        self.set_state(DeviceState::Probing);
+        let _ = self.transaction();
+        self.dev.sleep();
+        match self.transaction() {
+            Ok(slots) => {
+                self.update_slots(&slots);
+                self.connection.connected();
+                self.set_state(DeviceState::Connected);
+            }
+            Err(e) => {
+                self.last_error = Some(e);
+                self.connection.probe_failed(self.timestamp_ms);
+                self.set_state(DeviceState::Disconnected);
+            }
+        }
+    }
+    /// Polling all slots, timestamp advances by poll interval
+    pub fn poll(&mut self) {
+        self.poll_at(self.timestamp_ms.wrapping_add(self.poll_interval_ms));
+    }
+    /// Polling at time `now_ms` of monotonic clock.
+    /// Lost multitap is probed again when the delay of backoff expires
+    pub fn poll_at(&mut self, now_ms: u32) {
+        self.timestamp_ms = now_ms;
+        match self.state {
+            DeviceState::Connected => self.poll_slots(),
+            _ if self.connection.is_probe_due(now_ms) => self.connect(),
+            _ => {}
+        }
+    }
+    /// Set interval used by [`Multitap::poll`]
+    pub fn set_poll_interval(&mut self, interval_ms: u32) {
+        self.poll_interval_ms = interval_ms;
+    }
+    /// Set failures to lose multitap and delays between probes
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.connection.set_config(config);
+    }
+    /// Next change of connection state, changes are kept until read
+    pub fn next_state_change(&mut self) -> Option<StateChange> {
+        self.connection.pop()
+    }
+    /// Number of failed polls in a row
+    pub fn failures(&self) -> u8 {
+        self.connection.failures()
+    }
+    /// Pad in slot 0 - 3 (A - D)
+    pub fn pad(&self, slot: usize) -> &TapPad {
+        &self.pads[slot]
+    }
+    pub fn pads(&self) -> &[TapPad; TAP_SLOTS] {
+        &self.pads
+    }
+    /// Set sticks processing of pad in slot 0 - 3
+    pub fn set_stick_processor(&mut self, slot: usize, processor: StickProcessor) {
+        self.pads[slot].stick_processor = processor;
+    }
+    /// Error of the last failed transaction, cleared by a successful one
+    pub fn last_error(&self) -> Option<&ControllerError<Dev::Error>> {
+        self.last_error.as_ref()
+    }
+
+    /// Slots keep the last values until multitap is lost after several failures in a row
+    fn poll_slots(&mut self) {
+        match self.transaction() {
+            Ok(slots) => {
+                self.update_slots(&slots);
+                self.connection.poll_ok();
+            }
+            Err(e) => {
+                self.last_error = Some(e);
+                if self.connection.poll_failed(self.timestamp_ms) {
+                    self.pads.iter_mut().for_each(TapPad::disconnect);
+                    self.set_state(DeviceState::Lost);
+                }
+            }
+        }
+    }
+    fn update_slots(&mut self, slots: &[[u8; SLOT_SIZE]; TAP_SLOTS]) {
+        for (pad, data) in self.pads.iter_mut().zip(slots.iter()) {
+            pad.update(data);
+        }
+        self.last_error = None;
+    }
+    /// Change state of connection and remember the change
+    fn set_state(&mut self, state: DeviceState) {
+        if self.state != state {
+            self.connection.push(StateChange {
+                from: self.state,
+                to: state,
+                at_ms: self.timestamp_ms,
+            });
+            self.state = state;
+        }
+    }
+    /// Poll command with the TAP byte, slot data follows the header
+    fn transaction(&mut self) -> CResult<[[u8; SLOT_SIZE]; TAP_SLOTS], Dev::Error> {
+        self.dev.start_cmd().map_err(ControllerError::Transport)?;
+        let result = self.exchange_slots();
+        self.dev.stop_cmd().map_err(ControllerError::Transport)?;
+        result
+    }
+    fn exchange_slots(&mut self) -> CResult<[[u8; SLOT_SIZE]; TAP_SLOTS], Dev::Error> {
+        self.tx_rx(0x01)?;
+        let id = self.tx_rx(SLOT_POLL[0])?;
+        let ack = self.tx_rx(TAP_ALL_SLOTS)?;
+        match (id, ack) {
+            (TAP_ID, 0x5A) => {}
+            (0xFF, _) => return Err(ControllerError::NoResponse),
+            _ => return Err(ControllerError::BadHeader),
+        }
+        let mut slots = [[0u8; SLOT_SIZE]; TAP_SLOTS];
+        for slot in slots.iter_mut() {
+            for (rx, tx) in slot.iter_mut().zip(SLOT_POLL) {
+                *rx = self.tx_rx(tx)?;
+            }
+        }
+        Ok(slots)
+    }
+    fn tx_rx(&mut self, byte: u8) -> CResult<u8, Dev::Error> {
+        self.dev.send_8bit(byte).map_err(ControllerError::Transport)
+    }
+}
+
+impl Gamepad for TapPad {
+    fn is_analog(&self) -> bool {
+        matches!(self.cmode, DeviceMode::Analog)
+    }
+    fn is_active(&self) -> bool {
+        self.buttons.is_active()
+    }
+    fn is_changed(&self, btn: Button) -> bool {
+        self.buttons.is_changed(btn as u16)
+    }
+    fn is_pressed(&self, btn: Button) -> bool {
+        self.buttons.is_pressed(btn as u16)
+    }
+    fn is_pressed_all(&self, buttons: ButtonSet) -> bool {
+        self.buttons.pressed().all(buttons)
+    }
+    fn is_pressed_any(&self, buttons: ButtonSet) -> bool {
+        self.buttons.pressed().any(buttons)
+    }
+    fn is_down(&self, btn: Button) -> bool {
+        self.buttons.is_down(btn as u16)
+    }
+    fn is_up(&self, btn: Button) -> bool {
+        self.buttons.is_up(btn as u16)
+    }
+    fn pressed_buttons(&self, filter: ButtonSet) -> ButtonSet {
+        self.buttons.pressed() & filter
+    }
+    fn analog_sticks(&self) -> AnalogSticks {
+        self.analog
+    }
+    fn processed_sticks(&self) -> ProcessedSticks {
+        match self.is_analog() {
+            true => self.stick_processor.process(self.analog),
+            false => ProcessedSticks::default(),
+        }
+    }
+    /// Multitap does not pass pressures, 255 for pressed button
+    fn pressure(&self, btn: Button) -> u8 {
+        self.button_pressures().get(btn)
+    }
+    fn button_pressures(&self) -> ButtonPressures {
+        ButtonPressures::from_buttons(self.buttons.pressed())
+    }
+    fn whammy_bar(&self) -> u8 {
+        self.analog.ly
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{create_mock_multitap, MockPad};
+
+    #[test]
+    fn slot_mapping() {
+        let (a, c) = (MockPad::dualshock2(), MockPad::dualshock());
+        let mut tap = create_mock_multitap([Some(&a), None, Some(&c), None]);
+        tap.poll_at(0);
+        assert_eq!(DeviceState::Connected, tap.state);
+        a.press(Button::Start);
+        c.press(Button::Cross);
+        c.press_analog_button();
+        c.set_sticks(AnalogSticks::new(0x10, 0x20, 0x30, 0x40));
+        tap.poll();
+
+        assert!(tap.pad(0).is_pressed(Button::Start) && !tap.pad(0).is_analog());
+        let pad = tap.pad(2);
+        assert!(pad.is_down(Button::Cross) && !pad.is_pressed(Button::Start));
+        assert_eq!(
+            ButtonSet::from(Button::Cross),
+            pad.pressed_buttons(ButtonSet::ALL)
+        );
+        assert_eq!(255, pad.pressure(Button::Cross));
+        assert!(pad.is_analog());
+        assert_eq!(
+            AnalogSticks::new(0x10, 0x20, 0x30, 0x40),
+            pad.analog_sticks()
+        );
+
+        c.release(Button::Cross);
+        tap.poll();
+        assert!(tap.pad(2).is_up(Button::Cross) && !tap.pad(2).is_active());
+    }
+
+    #[test]
+    fn empty_slot() {
+        let a = MockPad::dualshock2();
+        let mut tap = create_mock_multitap([Some(&a), None, None, None]);
+        tap.poll_at(0);
+        for pad in &tap.pads()[1..] {
+            assert_eq!(DeviceState::Disconnected, pad.state);
+            assert_eq!(DeviceMode::Unknown, pad.cmode);
+            assert!(!pad.is_active());
+        }
+        // pad removed from a slot while multitap stays connected
+        a.press(Button::Circle);
+        tap.poll();
+        assert!(tap.pad(0).is_pressed(Button::Circle));
+        a.set_connected(false);
+        tap.poll();
+        assert_eq!(DeviceState::Connected, tap.state);
+        assert_eq!(DeviceState::Disconnected, tap.pad(0).state);
+        assert!(tap.pad(0).is_up(Button::Circle));
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        let a = MockPad::dualshock2();
+        let mut tap = create_mock_multitap([Some(&a), None, None, None]);
+        tap.poll_at(0);
+        while tap.next_state_change().is_some() {}
+
+        tap.dev.set_connected(false);
+        for t in [16, 32] {
+            tap.poll_at(t);
+            assert_eq!(DeviceState::Connected, tap.state);
+        }
+        tap.poll_at(48);
+        assert_eq!((DeviceState::Lost, 3), (tap.state, tap.failures()));
+        assert_eq!(DeviceState::Disconnected, tap.pad(0).state);
+
+        // probes follow after 100, 200 and 400 ms
+        let mut probes = [0u32; 4];
+        let mut count = 0;
+        for t in (64..=800).step_by(4) {
+            if 500 == t {
+                tap.dev.set_connected(true);
+            }
+            tap.poll_at(t);
+            while let Some(change) = tap.next_state_change() {
+                if DeviceState::Probing == change.to {
+                    probes[count] = change.at_ms;
+                    count += 1;
+                }
+            }
+        }
+        assert_eq!([64, 164, 364, 764], probes);
+        assert_eq!(DeviceState::Connected, tap.state);
+        assert_eq!(DeviceState::Connected, tap.pad(0).state);
+    }
+}
@@ -0,0 +1,497 @@
+use crate::buttons::{AnalogSticks, ButtonPressures, ButtonSet};
+use crate::commands::{
+    Command, DeviceError, DeviceMode, DeviceType, ErrorType, DATA_HEADER_SIZE, DATA_SIZE,
+    MODE_ANALOG, MODE_LOCK, MOTORS_UNMAP, POLL_ANALOG, TX_PS2,
+};
+use crate::controller::Controller;
+use crate::device::PsxTrasferData;
+use crate::driver::PsxDriver;
+use core::cell::RefCell;
+use embedded_hal::delay::DelayNs;
+
+/// Address of controller, the first byte of a packet
+const PAD_ADDRESS: u8 = 0x01;
+const PAYLOAD: usize = DATA_HEADER_SIZE as usize;
+
+/// Constants of DualShock 2, the second part differs from byte 4 by the index
+const CONST1: [[u8; 6]; 2] = [
+    [0x00, 0x00, 0x01, 0x02, 0x00, 0x0A],
+    [0x00, 0x00, 0x01, 0x01, 0x01, 0x14],
+];
+const CONST2: [u8; 6] = [0x00, 0x00, 0x02, 0x00, 0x01, 0x00];
+const CONST3: [[u8; 6]; 2] = [
+    [0x00, 0x00, 0x00, 0x04, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x07, 0x00, 0x00],
+];
+
+/// Creating an instance [`Controller`] connected to the peripheral in the same process
+pub fn create_loopback_controller(
+    peripheral: &RefCell<PsxPeripheral>,
+) -> Controller<PsxDriver<Loopback<'_>>> {
+    Controller::new(PsxDriver::new(Loopback::new(peripheral)))
+}
+
+/// Model answered by [`PadState`]
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) struct PadProfile {
+    /// Byte 3 of `QueryModelAndMode`
+    pub model: u8,
+    /// ID of devices without modes, neGcon answers 0x23
+    pub fixed_id: Option<u8>,
+    /// Device enters config mode
+    pub is_config: bool,
+    /// Device answers button pressures
+    pub is_pressures: bool,
+    /// Buttons held down by the device, Guitar Hero holds Left
+    pub held: u16,
+    /// Two parts of `GetConst1`, actuators
+    pub actuators: [[u8; 6]; 2],
+}
+
+impl PadProfile {
+    pub const DUALSHOCK2: Self = Self {
+        model: DeviceType::DualShock2 as u8,
+        fixed_id: None,
+        is_config: true,
+        is_pressures: true,
+        held: 0,
+        actuators: CONST1,
+    };
+}
+
+/// Byte-level state machine of a pad, shared by [`PsxPeripheral`] and the mock pad
+pub(crate) struct PadState {
+    profile: PadProfile,
+    /// Active low as in the response
+    buttons: u16,
+    sticks: AnalogSticks,
+    pressures: [u8; 12],
+    is_analog: bool,
+    is_locked: bool,
+    is_config: bool,
+    is_pressures: bool,
+    motor_map: [u8; 6],
+    motors: (u8, u8),
+    // current packet
+    cursor: usize,
+    len: usize,
+    is_selected: bool,
+    cmd: Option<Command>,
+    rx: [u8; DATA_SIZE],
+    tx: [u8; DATA_SIZE],
+}
+
+impl PadState {
+    /// Device in digital mode, as after power on
+    pub fn new(profile: PadProfile) -> Self {
+        Self {
+            profile,
+            buttons: 0xFFFF,
+            sticks: AnalogSticks::default(),
+            pressures: [0u8; 12],
+            is_analog: false,
+            is_locked: false,
+            is_config: false,
+            is_pressures: false,
+            motor_map: MOTORS_UNMAP,
+            motors: (0, 0),
+            cursor: 0,
+            len: DATA_SIZE,
+            is_selected: false,
+            cmd: None,
+            rx: [0u8; DATA_SIZE],
+            tx: [0u8; DATA_SIZE],
+        }
+    }
+    #[cfg(any(test, feature = "mock"))]
+    pub fn buttons(&self) -> u16 {
+        self.buttons
+    }
+    /// Buttons, active low
+    pub fn set_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons;
+    }
+    pub fn pressures_mut(&mut self) -> &mut [u8; 12] {
+        &mut self.pressures
+    }
+    pub fn set_sticks(&mut self, sticks: AnalogSticks) {
+        self.sticks = sticks;
+    }
+    /// Analog button toggles the mode, unless it is locked by the host
+    pub fn press_analog_button(&mut self) -> bool {
+        if !self.is_locked {
+            self.is_analog = !self.is_analog;
+            self.is_pressures &= self.is_analog;
+        }
+        self.is_analog
+    }
+    /// Power loss resets device to digital mode
+    #[cfg(any(test, feature = "mock"))]
+    pub fn power_off(&mut self) {
+        *self = Self {
+            buttons: self.buttons,
+            sticks: self.sticks,
+            pressures: self.pressures,
+            ..Self::new(self.profile)
+        };
+    }
+    pub fn motors(&self) -> (u8, u8) {
+        self.motors
+    }
+    pub fn mode(&self) -> DeviceMode {
+        DeviceMode::from(self.id())
+    }
+    pub fn is_analog(&self) -> bool {
+        self.is_analog
+    }
+    pub fn is_locked(&self) -> bool {
+        self.is_locked
+    }
+    pub fn is_config(&self) -> bool {
+        self.is_config
+    }
+    pub fn is_pressures(&self) -> bool {
+        self.is_pressures
+    }
+    /// The packet is addressed to the pad and has the complete header
+    pub fn is_packet(&self) -> bool {
+        self.is_selected && self.cursor >= PAYLOAD
+    }
+
+    /// ATT low, start of packet
+    pub fn select(&mut self) {
+        self.cursor = 0;
+        self.len = DATA_SIZE;
+        self.is_selected = false;
+        self.cmd = None;
+        self.rx = [0u8; DATA_SIZE];
+    }
+    /// ATT high, the received command is applied
+    pub fn deselect(&mut self) {
+        if self.is_packet() {
+            self.execute();
+        }
+        self.is_selected = false;
+    }
+    /// Byte of CMD in, byte of DAT out
+    pub fn exchange(&mut self, byte: u8) -> u8 {
+        let n = self.cursor;
+        self.cursor += 1;
+        if n >= DATA_SIZE {
+            return 0xFF;
+        }
+        self.rx[n] = byte;
+        match n {
+            0 => {
+                self.is_selected = PAD_ADDRESS == byte;
+                0xFF
+            }
+            _ if !self.is_selected => 0xFF,
+            1 => {
+                let id = self.id();
+                self.len = PAYLOAD + 2 * (id & 0x0F) as usize;
+                self.cmd = Command::try_from(byte).ok();
+                self.prepare();
+                id
+            }
+            2 => TX_PS2,
+            3 => {
+                let answer = self.tx[n];
+                self.on_index(byte);
+                answer
+            }
+            _ => self.tx[n],
+        }
+    }
+    /// ACK after the last byte, all bytes of the packet except the last
+    pub fn is_acked(&self) -> bool {
+        self.is_selected && self.cursor < self.len
+    }
+
+    /// ID byte: upper nibble is mode, lower nibble is payload size in u16
+    fn id(&self) -> u8 {
+        if let Some(id) = self.profile.fixed_id {
+            return id;
+        }
+        match (self.is_config, self.is_analog, self.is_pressures) {
+            (true, _, _) => 0xF3,
+            (false, true, true) => 0x79,
+            (false, true, false) => 0x73,
+            (false, false, _) => 0x41,
+        }
+    }
+    /// Response payload, prepared when the command byte is received
+    fn prepare(&mut self) {
+        self.tx = [0u8; DATA_SIZE];
+        let payload = &mut self.tx[PAYLOAD..];
+        if !self.is_config {
+            let [lo, hi] = (self.buttons & !self.profile.held).to_le_bytes();
+            let s = self.sticks;
+            payload[..6].copy_from_slice(&[lo, hi, s.rx, s.ry, s.lx, s.ly]);
+            payload[6..18].copy_from_slice(&self.pressures);
+            return;
+        }
+        let response = match self.cmd {
+            // DualShock 2 answers zeros in digital mode
+            Some(Command::QueryDS2AnalogMode) if self.profile.is_pressures && self.is_analog => {
+                [0xFF, 0xFF, 0x03, 0x00, 0x00, 0x5A]
+            }
+            Some(Command::QueryModelAndMode) => [
+                self.profile.model,
+                0x02,
+                self.is_analog as u8,
+                0x02,
+                0x01,
+                0x00,
+            ],
+            Some(Command::GetConst1) => self.profile.actuators[0],
+            Some(Command::GetConst2) => CONST2,
+            Some(Command::GetConst3) => CONST3[0],
+            Some(Command::MapMotors) => self.motor_map,
+            Some(Command::SetupPoll) => [0x00, 0x00, 0x00, 0x00, 0x00, 0x5A],
+            _ => [0u8; 6],
+        };
+        payload[..6].copy_from_slice(&response);
+    }
+    /// Second part of constants, the index is received with byte 3
+    fn on_index(&mut self, index: u8) {
+        if !self.is_config || 0x01 != index {
+            return;
+        }
+        match self.cmd {
+            Some(Command::GetConst1) => {
+                self.tx[PAYLOAD..].copy_from_slice(&pad(self.profile.actuators[1]))
+            }
+            Some(Command::GetConst3) => self.tx[PAYLOAD..].copy_from_slice(&pad(CONST3[1])),
+            _ => {}
+        }
+    }
+    fn execute(&mut self) {
+        let rx = self.rx;
+        let payload = &rx[PAYLOAD..];
+        match (self.is_config, self.cmd) {
+            (false, Some(Command::MainPoll)) => self.drive_motors(payload),
+            (_, Some(Command::Config)) if self.profile.is_config => {
+                self.is_config = 0x01 == payload[0]
+            }
+            (true, Some(Command::SetModeAndLock)) => {
+                self.is_analog = MODE_ANALOG == payload[0];
+                self.is_locked = MODE_LOCK == payload[1];
+                self.is_pressures &= self.is_analog;
+            }
+            (true, Some(Command::MapMotors)) => self.motor_map.copy_from_slice(&payload[..6]),
+            (true, Some(Command::SetupPoll)) if self.profile.is_pressures => {
+                let mask = u32::from_le_bytes([payload[0], payload[1], payload[2], 0]);
+                let analog = u32::from_le_bytes([POLL_ANALOG[0], POLL_ANALOG[1], 0, 0]);
+                self.is_pressures = self.is_analog && mask > analog;
+            }
+            _ => {}
+        }
+    }
+    fn drive_motors(&mut self, payload: &[u8]) {
+        for (i, map) in self.motor_map.iter().enumerate() {
+            match map {
+                0x00 => self.motors.0 = payload[i],
+                0x01 => self.motors.1 = payload[i],
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Response of 6 bytes in the payload of 18 bytes
+fn pad(response: [u8; 6]) -> [u8; DATA_SIZE - PAYLOAD] {
+    let mut payload = [0u8; DATA_SIZE - PAYLOAD];
+    payload[..6].copy_from_slice(&response);
+    payload
+}
+
+/// Device side of the bus, answers a console as DualShock 2.
+///
+/// The bus glue calls [`select`](Self::select) on ATT low, [`exchange`](Self::exchange)
+/// for every byte of CMD and [`deselect`](Self::deselect) on ATT high,
+/// the application sets buttons, sticks and pressures between packets.
+pub struct PsxPeripheral {
+    pad: PadState,
+    buttons: ButtonSet,
+    pressures: Option<ButtonPressures>,
+}
+
+impl Default for PsxPeripheral {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PsxPeripheral {
+    /// Device in digital mode, as after power on
+    pub fn new() -> Self {
+        Self {
+            pad: PadState::new(PadProfile::DUALSHOCK2),
+            buttons: ButtonSet::EMPTY,
+            pressures: None,
+        }
+    }
+    /// Pressed buttons
+    pub fn set_buttons(&mut self, pressed: ButtonSet) {
+        self.buttons = pressed;
+        self.pad.set_buttons(!pressed.bits());
+        self.update_pressures();
+    }
+    pub fn set_sticks(&mut self, sticks: AnalogSticks) {
+        self.pad.set_sticks(sticks);
+    }
+    /// Pressures of buttons, `None` - 255 for pressed buttons
+    pub fn set_pressures(&mut self, pressures: Option<ButtonPressures>) {
+        self.pressures = pressures;
+        self.update_pressures();
+    }
+    /// Analog button toggles the mode, unless it is locked by the console
+    pub fn press_analog_button(&mut self) -> bool {
+        self.pad.press_analog_button()
+    }
+    /// Values received for small and large motors
+    pub fn motors(&self) -> (u8, u8) {
+        self.pad.motors()
+    }
+    pub fn mode(&self) -> DeviceMode {
+        self.pad.mode()
+    }
+    pub fn is_analog(&self) -> bool {
+        self.pad.is_analog()
+    }
+    pub fn is_locked(&self) -> bool {
+        self.pad.is_locked()
+    }
+    pub fn is_config(&self) -> bool {
+        self.pad.is_config()
+    }
+    pub fn is_pressures(&self) -> bool {
+        self.pad.is_pressures()
+    }
+
+    /// ATT low, start of packet
+    pub fn select(&mut self) {
+        self.pad.select();
+    }
+    /// ATT high, the received command is applied
+    pub fn deselect(&mut self) {
+        self.pad.deselect();
+    }
+    /// Byte of CMD in, byte of DAT out
+    pub fn exchange(&mut self, byte: u8) -> u8 {
+        self.pad.exchange(byte)
+    }
+    /// ACK after the last byte, all bytes of the packet except the last
+    pub fn is_acked(&self) -> bool {
+        self.pad.is_acked()
+    }
+
+    fn update_pressures(&mut self) {
+        let pressures = self
+            .pressures
+            .unwrap_or(ButtonPressures::from_buttons(self.buttons));
+        self.pad
+            .pressures_mut()
+            .copy_from_slice(pressures.as_bytes());
+    }
+}
+
+/// In-process bus between [`PsxDriver`] and [`PsxPeripheral`], delays are skipped
+pub struct Loopback<'a> {
+    peripheral: &'a RefCell<PsxPeripheral>,
+}
+
+impl<'a> Loopback<'a> {
+    pub fn new(peripheral: &'a RefCell<PsxPeripheral>) -> Self {
+        Self { peripheral }
+    }
+}
+
+impl DelayNs for Loopback<'_> {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl ErrorType for Loopback<'_> {
+    type Error = DeviceError;
+}
+
+impl PsxTrasferData for Loopback<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.peripheral.borrow_mut().select();
+        Ok(())
+    }
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        self.peripheral.borrow_mut().deselect();
+        Ok(())
+    }
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        Ok(self.peripheral.borrow_mut().exchange(byte))
+    }
+    fn last_ack(&self) -> Option<bool> {
+        Some(self.peripheral.borrow().is_acked())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::Button;
+    use crate::driver::Driver;
+    use crate::Gamepad;
+
+    #[test]
+    fn driver_reads_model_and_constants() {
+        let peripheral = RefCell::new(PsxPeripheral::new());
+        let mut driver = PsxDriver::new(Loopback::new(&peripheral));
+        driver.initialize().unwrap();
+        assert_eq!(DeviceMode::Digital, driver.current_mode());
+        let (model, is_led) = driver.query_model_and_mode().unwrap();
+        assert_eq!((DeviceType::DualShock2 as u8, 0x00), (model, is_led));
+        let info = driver.read_device_info().unwrap();
+        assert_eq!(
+            [0x00, 0x01, 0x02, 0x00, 0x0A, 0x00, 0x01, 0x01, 0x01, 0x14],
+            info.unknown1
+        );
+        assert_eq!([0x00, 0x02, 0x00, 0x01, 0x00], info.unknown2);
+        assert!(!peripheral.borrow().is_config());
+    }
+
+    #[test]
+    fn controller_polls_peripheral() {
+        let peripheral = RefCell::new(PsxPeripheral::new());
+        let mut controller = create_loopback_controller(&peripheral);
+        controller.connect();
+        assert_eq!(crate::commands::DeviceState::Connected, controller.state);
+        assert_eq!(DeviceType::DualShock2, controller.ctype);
+
+        peripheral.borrow_mut().set_buttons(Button::Cross.into());
+        controller.poll();
+        assert!(controller.is_pressed(Button::Cross));
+        assert!(!controller.is_analog());
+
+        controller.set_mode(DeviceMode::Analog, true);
+        controller.enable_pressures(true);
+        let pressures = ButtonPressures::new([0x10; 12]);
+        peripheral.borrow_mut().set_pressures(Some(pressures));
+        peripheral
+            .borrow_mut()
+            .set_sticks(AnalogSticks::new(0x00, 0x40, 0x80, 0xFF));
+        controller.poll();
+        assert!(peripheral.borrow().is_locked());
+        assert!(peripheral.borrow().is_pressures());
+        assert!(controller.is_analog());
+        assert_eq!(0x10, controller.pressure(Button::Cross));
+        assert_eq!(
+            AnalogSticks::new(0x00, 0x40, 0x80, 0xFF),
+            controller.analog_sticks()
+        );
+        assert!(peripheral.borrow_mut().press_analog_button());
+
+        controller.set_rumble(true, 0xFF);
+        controller.poll();
+        assert_eq!((0xFF, 0xFF), peripheral.borrow().motors());
+    }
+}
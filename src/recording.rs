@@ -0,0 +1,486 @@
+//! Recording of the bus traffic and its replay on a host.
+//!
+//! [`RecordingTransport`] wraps a transport and passes every `start_cmd`, `send_8bit`
+//! and `stop_cmd` to a [`Recorder`]. Events are stored in a compact binary form:
+//! `0x01 TIME[4]` - start of packet with time in µs, `0x1A CMD DAT` - exchanged byte
+//! where `A` is ACK (0 - no ACK line, 1 - acked, 2 - not acked), `0x02` - end of packet,
+//! `0x03` - the transport failed. [`ReplayTransport`] plays the recording back into `PsxDriver`.
+use crate::commands::{DeviceError, ErrorType};
+use crate::controller::Controller;
+use crate::device::{ClockControl, PsxTrasferData, TimingProfile};
+use crate::driver::PsxDriver;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::ErrorKind;
+
+/// Size of the largest encoded event
+pub const MAX_EVENT_SIZE: usize = 5;
+
+const TAG_START: u8 = 0x01;
+const TAG_STOP: u8 = 0x02;
+const TAG_ERROR: u8 = 0x03;
+const TAG_BYTE: u8 = 0x10;
+
+/// Operation of the transport
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// ATT low, time in µs
+    Start {
+        time_us: u32,
+    },
+    Byte {
+        cmd: u8,
+        dat: u8,
+        ack: Option<bool>,
+    },
+    /// ATT high
+    Stop,
+    /// The operation at this place returned an error
+    Error,
+}
+
+impl Event {
+    /// Write the event to `out`, returns its size
+    pub fn encode(&self, out: &mut [u8; MAX_EVENT_SIZE]) -> usize {
+        match *self {
+            Self::Start { time_us } => {
+                out[0] = TAG_START;
+                out[1..].copy_from_slice(&time_us.to_le_bytes());
+                MAX_EVENT_SIZE
+            }
+            Self::Byte { cmd, dat, ack } => {
+                let ack = match ack {
+                    None => 0,
+                    Some(true) => 1,
+                    Some(false) => 2,
+                };
+                out[..3].copy_from_slice(&[TAG_BYTE | ack, cmd, dat]);
+                3
+            }
+            Self::Stop => {
+                out[0] = TAG_STOP;
+                1
+            }
+            Self::Error => {
+                out[0] = TAG_ERROR;
+                1
+            }
+        }
+    }
+    /// Read the event at the beginning of `data`, returns it with its size
+    pub fn decode(data: &[u8]) -> Option<(Self, usize)> {
+        match *data {
+            [TAG_START, a, b, c, d, ..] => Some((
+                Self::Start {
+                    time_us: u32::from_le_bytes([a, b, c, d]),
+                },
+                MAX_EVENT_SIZE,
+            )),
+            [TAG_STOP, ..] => Some((Self::Stop, 1)),
+            [TAG_ERROR, ..] => Some((Self::Error, 1)),
+            [tag, cmd, dat, ..] if TAG_BYTE == tag & 0xF0 => {
+                let ack = match tag & 0x0F {
+                    0 => None,
+                    1 => Some(true),
+                    2 => Some(false),
+                    _ => return None,
+                };
+                Some((Self::Byte { cmd, dat, ack }, 3))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Events of the recording, it ends on the first broken event
+pub fn events(data: &[u8]) -> Events<'_> {
+    Events { data }
+}
+
+pub struct Events<'a> {
+    data: &'a [u8],
+}
+
+impl Iterator for Events<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let (event, len) = Event::decode(self.data)?;
+        self.data = &self.data[len..];
+        Some(event)
+    }
+}
+
+/// Storage of the recorded events
+pub trait Recorder {
+    fn record(&mut self, event: Event);
+}
+
+/// Recording in memory, it stops when the buffer is full
+pub struct RecordBuffer<const N: usize> {
+    data: [u8; N],
+    len: usize,
+    is_overflow: bool,
+}
+
+impl<const N: usize> Default for RecordBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RecordBuffer<N> {
+    pub fn new() -> Self {
+        Self {
+            data: [0u8; N],
+            len: 0,
+            is_overflow: false,
+        }
+    }
+    /// Encoded events
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+    /// Events were dropped because the buffer is full
+    pub fn is_overflow(&self) -> bool {
+        self.is_overflow
+    }
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.is_overflow = false;
+    }
+}
+
+impl<const N: usize> Recorder for RecordBuffer<N> {
+    fn record(&mut self, event: Event) {
+        let mut out = [0u8; MAX_EVENT_SIZE];
+        let len = event.encode(&mut out);
+        if self.is_overflow || self.len + len > N {
+            self.is_overflow = true;
+            return;
+        }
+        self.data[self.len..self.len + len].copy_from_slice(&out[..len]);
+        self.len += len;
+    }
+}
+
+/// Recording to a file, socket or any other writer
+#[cfg(feature = "std")]
+pub struct IoRecorder<W> {
+    writer: W,
+    errors: u32,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> IoRecorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, errors: 0 }
+    }
+    /// Events lost on write errors
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Recorder for IoRecorder<W> {
+    fn record(&mut self, event: Event) {
+        let mut out = [0u8; MAX_EVENT_SIZE];
+        let len = event.encode(&mut out);
+        if self.writer.write_all(&out[..len]).is_err() {
+            self.errors += 1;
+        }
+    }
+}
+
+/// Transport passing every operation to the recorder.
+///
+/// Time of packets is counted by the delays passed through the transport,
+/// [`set_time_us`](Self::set_time_us) syncs it with the clock of the application.
+pub struct RecordingTransport<T, R> {
+    dev: T,
+    recorder: R,
+    time_ns: u64,
+}
+
+impl<T: PsxTrasferData, R: Recorder> RecordingTransport<T, R> {
+    pub fn new(dev: T, recorder: R) -> Self {
+        Self {
+            dev,
+            recorder,
+            time_ns: 0,
+        }
+    }
+    pub fn recorder(&self) -> &R {
+        &self.recorder
+    }
+    pub fn recorder_mut(&mut self) -> &mut R {
+        &mut self.recorder
+    }
+    pub fn set_time_us(&mut self, time_us: u32) {
+        self.time_ns = time_us as u64 * 1000;
+    }
+    pub fn release(self) -> (T, R) {
+        (self.dev, self.recorder)
+    }
+    fn time_us(&self) -> u32 {
+        (self.time_ns / 1000) as u32
+    }
+}
+
+impl<T: DelayNs, R> DelayNs for RecordingTransport<T, R> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.time_ns += ns as u64;
+        self.dev.delay_ns(ns);
+    }
+}
+
+impl<T: ErrorType, R> ErrorType for RecordingTransport<T, R> {
+    type Error = T::Error;
+}
+
+impl<T: PsxTrasferData, R: Recorder> PsxTrasferData for RecordingTransport<T, R> {
+    const SLEEP_NS: u32 = T::SLEEP_NS;
+
+    fn start_cmd(&mut self) -> Result<(), Self::Error> {
+        let result = self.dev.start_cmd();
+        let event = match result {
+            Ok(_) => Event::Start {
+                time_us: self.time_us(),
+            },
+            Err(_) => Event::Error,
+        };
+        self.recorder.record(event);
+        result
+    }
+    fn stop_cmd(&mut self) -> Result<(), Self::Error> {
+        let result = self.dev.stop_cmd();
+        let event = match result {
+            Ok(_) => Event::Stop,
+            Err(_) => Event::Error,
+        };
+        self.recorder.record(event);
+        result
+    }
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, Self::Error> {
+        let result = self.dev.send_8bit(byte);
+        let event = match result {
+            Ok(dat) => Event::Byte {
+                cmd: byte,
+                dat,
+                ack: self.dev.last_ack(),
+            },
+            Err(_) => Event::Error,
+        };
+        self.recorder.record(event);
+        result
+    }
+    fn last_ack(&self) -> Option<bool> {
+        self.dev.last_ack()
+    }
+}
+
+impl<T: ClockControl, R> ClockControl for RecordingTransport<T, R> {
+    fn timing(&self) -> TimingProfile {
+        self.dev.timing()
+    }
+    fn set_timing(&mut self, timing: TimingProfile) {
+        self.dev.set_timing(timing);
+    }
+}
+
+/// Creating an instance [`Controller`] playing the recording back
+pub fn create_replay_controller(data: &[u8]) -> Controller<PsxDriver<ReplayTransport<'_>>> {
+    Controller::new(PsxDriver::new(ReplayTransport::new(data)))
+}
+
+/// Transport answering from the recording, delays are skipped.
+///
+/// Bytes are answered in the recorded order, a packet longer than recorded
+/// gets 0xFF as from an absent device, recorded errors are returned at their place.
+pub struct ReplayTransport<'a> {
+    data: &'a [u8],
+    ack: Option<bool>,
+    mismatches: u32,
+}
+
+impl<'a> ReplayTransport<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            ack: None,
+            mismatches: 0,
+        }
+    }
+    /// Bytes of CMD which differ from the recording, and skipped or missing bytes
+    pub fn mismatches(&self) -> u32 {
+        self.mismatches
+    }
+    /// All events are played
+    pub fn is_finished(&self) -> bool {
+        self.peek().is_none()
+    }
+    fn peek(&self) -> Option<(Event, usize)> {
+        Event::decode(self.data)
+    }
+    fn consume(&mut self, len: usize) {
+        self.data = &self.data[len..];
+    }
+    fn error() -> DeviceError {
+        DeviceError::Pin(ErrorKind::Other)
+    }
+}
+
+impl DelayNs for ReplayTransport<'_> {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+impl ErrorType for ReplayTransport<'_> {
+    type Error = DeviceError;
+}
+
+impl PsxTrasferData for ReplayTransport<'_> {
+    const SLEEP_NS: u32 = 8_000_000;
+
+    fn start_cmd(&mut self) -> Result<(), DeviceError> {
+        self.ack = None;
+        while let Some((event, len)) = self.peek() {
+            self.consume(len);
+            match event {
+                Event::Start { .. } => return Ok(()),
+                Event::Error => return Err(Self::error()),
+                // rest of the previous packet
+                _ => self.mismatches += 1,
+            }
+        }
+        Ok(())
+    }
+    fn stop_cmd(&mut self) -> Result<(), DeviceError> {
+        while let Some((event, len)) = self.peek() {
+            match event {
+                Event::Start { .. } => break,
+                Event::Stop => {
+                    self.consume(len);
+                    break;
+                }
+                Event::Error => {
+                    self.consume(len);
+                    return Err(Self::error());
+                }
+                Event::Byte { .. } => {
+                    self.consume(len);
+                    self.mismatches += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn send_8bit(&mut self, byte: u8) -> Result<u8, DeviceError> {
+        match self.peek() {
+            Some((Event::Byte { cmd, dat, ack }, len)) => {
+                self.consume(len);
+                self.mismatches += (cmd != byte) as u32;
+                self.ack = ack;
+                Ok(dat)
+            }
+            Some((Event::Error, len)) => {
+                self.consume(len);
+                Err(Self::error())
+            }
+            _ => {
+                self.mismatches += 1;
+                self.ack = Some(false);
+                Ok(0xFF)
+            }
+        }
+    }
+    fn last_ack(&self) -> Option<bool> {
+        self.ack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::{AnalogSticks, Button};
+    use crate::mock::{create_mock_controller, MockPad};
+    use crate::Gamepad;
+
+    #[test]
+    fn encode_decode() {
+        let list = [
+            Event::Start {
+                time_us: 0x1234_5678,
+            },
+            Event::Byte {
+                cmd: 0x01,
+                dat: 0xFF,
+                ack: None,
+            },
+            Event::Byte {
+                cmd: 0x42,
+                dat: 0x73,
+                ack: Some(true),
+            },
+            Event::Byte {
+                cmd: 0x00,
+                dat: 0x80,
+                ack: Some(false),
+            },
+            Event::Error,
+            Event::Stop,
+        ];
+        let mut rec = RecordBuffer::<64>::new();
+        for event in list {
+            let mut out = [0u8; MAX_EVENT_SIZE];
+            let len = event.encode(&mut out);
+            assert_eq!(Some((event, len)), Event::decode(&out[..len]));
+            rec.record(event);
+        }
+        assert_eq!(&[0x01, 0x78, 0x56, 0x34, 0x12], &rec.as_bytes()[..5]);
+        assert!(events(rec.as_bytes()).eq(list));
+        // unknown ACK and cut event
+        assert_eq!(None, Event::decode(&[0x13, 0x00, 0x00]));
+        assert_eq!(None, Event::decode(&[TAG_START, 0x00]));
+    }
+
+    #[test]
+    fn buffer_overflow() {
+        let mut rec = RecordBuffer::<6>::new();
+        rec.record(Event::Start { time_us: 5 });
+        rec.record(Event::Stop);
+        assert!(!rec.is_overflow());
+        // later events are dropped even if they fit
+        rec.record(Event::Start { time_us: 6 });
+        rec.record(Event::Stop);
+        assert!(rec.is_overflow());
+        assert!(events(rec.as_bytes()).eq([Event::Start { time_us: 5 }, Event::Stop]));
+        rec.clear();
+        assert!(!rec.is_overflow() && rec.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let pad = MockPad::dualshock2();
+        let mut controller = create_mock_controller(&pad).record(RecordBuffer::<4096>::new());
+        controller.connect();
+        pad.press(Button::Cross);
+        pad.press_analog_button();
+        pad.set_sticks(AnalogSticks::new(0x10, 0x20, 0x30, 0x40));
+        controller.poll();
+        controller.poll();
+        assert!(!controller.recorder().is_overflow());
+
+        let mut replay = create_replay_controller(controller.recorder().as_bytes());
+        replay.connect();
+        replay.poll();
+        replay.poll();
+        assert_eq!(0, replay.replay().mismatches());
+        assert!(replay.replay().is_finished());
+        assert_eq!(controller.snapshot(), replay.snapshot());
+        assert_eq!(controller.model, replay.model);
+        assert!(replay.is_pressed(Button::Cross) && replay.is_analog());
+    }
+}
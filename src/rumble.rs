@@ -0,0 +1,95 @@
+/// Values of the vibration motors
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Motors {
+    /// Small motor, on or off
+    pub small: bool,
+    /// Large motor speed, 0 - stop
+    pub large: u8,
+}
+impl Motors {
+    pub fn new(small: bool, large: u8) -> Self {
+        Self { small, large }
+    }
+    /// Bytes of the poll command mapped by MapMotors
+    pub(crate) fn tx_values(&self) -> (u8, u8) {
+        let small = match self.small {
+            true => 0xFF,
+            false => 0x00,
+        };
+        (small, self.large)
+    }
+}
+
+/// Rumble state, a timed effect overrides the steady values until it expires
+#[derive(Copy, Clone, Default)]
+pub(crate) struct Rumble {
+    steady: Motors,
+    effect: Motors,
+    effect_ms: u32,
+    /// The effect was sent, its time is counted
+    is_started: bool,
+}
+impl Rumble {
+    pub fn set(&mut self, motors: Motors) {
+        self.steady = motors;
+    }
+    pub fn pulse(&mut self, motors: Motors, duration_ms: u32) {
+        self.effect = motors;
+        self.effect_ms = duration_ms;
+        self.is_started = false;
+    }
+    pub fn stop(&mut self) {
+        *self = Self::default();
+    }
+    /// Motor values for the next poll
+    pub fn current(&self) -> Motors {
+        match self.effect_ms {
+            0 => self.steady,
+            _ => self.effect,
+        }
+    }
+    /// Motor values sent by the poll, a timed effect starts when it is sent first
+    pub fn next(&mut self) -> Motors {
+        self.is_started |= self.effect_ms > 0;
+        self.current()
+    }
+    /// Advance timed effect by elapsed time between polls
+    pub fn tick(&mut self, elapsed_ms: u32) {
+        if self.is_started {
+            self.effect_ms = self.effect_ms.saturating_sub(elapsed_ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_overrides_steady() {
+        let mut rumble = Rumble::default();
+        rumble.set(Motors::new(false, 0x40));
+        rumble.pulse(Motors::new(true, 0xFF), 32);
+        assert_eq!((0xFF, 0xFF), rumble.next().tx_values());
+        rumble.tick(16);
+        assert_eq!(Motors::new(true, 0xFF), rumble.next());
+        rumble.tick(16);
+        assert_eq!(Motors::new(false, 0x40), rumble.next());
+        rumble.stop();
+        assert_eq!(Motors::default(), rumble.current());
+    }
+
+    #[test]
+    fn pulse_starts_when_sent() {
+        let mut rumble = Rumble::default();
+        rumble.pulse(Motors::new(true, 0x80), 20);
+        // time before the first poll with the effect is not counted
+        rumble.tick(16);
+        rumble.tick(16);
+        assert_eq!(Motors::new(true, 0x80), rumble.next());
+        rumble.tick(16);
+        assert_eq!(Motors::new(true, 0x80), rumble.next());
+        rumble.tick(16);
+        assert_eq!(Motors::default(), rumble.next());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    commands::DeviceState, controller::Controller, device::PsxTrasferData, driver::PsxDriver,
+};
+
+/// Default polling rate
+const POLL_RATE_HZ: u32 = 60;
+
+/// Unit of work of the scheduler, one packet at most
+#[derive(Copy, Clone)]
+pub(crate) enum Step {
+    Probe,
+    EnterConfig,
+    SetModeAndLock,
+    MapMotors,
+    SetupPoll,
+    QueryModel,
+    ExitConfig,
+    DeviceInfo(usize),
+    Finish,
+    Poll,
+}
+
+pub(crate) enum StepResult {
+    Sent,
+    /// Nothing to send, the next step runs immediately
+    Skipped,
+    Failed,
+}
+
+const CONNECT: &[Step] = &[
+    Step::Probe,
+    Step::EnterConfig,
+    Step::SetModeAndLock,
+    Step::MapMotors,
+    Step::SetupPoll,
+    Step::QueryModel,
+    Step::DeviceInfo(0),
+    Step::DeviceInfo(1),
+    Step::DeviceInfo(2),
+    Step::DeviceInfo(3),
+    Step::DeviceInfo(4),
+    Step::DeviceInfo(5),
+    Step::ExitConfig,
+    Step::Finish,
+];
+const SETUP: &[Step] = &[
+    Step::EnterConfig,
+    Step::SetModeAndLock,
+    Step::MapMotors,
+    Step::SetupPoll,
+    Step::ExitConfig,
+];
+const DEVICE_INFO: &[Step] = &[
+    Step::EnterConfig,
+    Step::DeviceInfo(0),
+    Step::DeviceInfo(1),
+    Step::DeviceInfo(2),
+    Step::DeviceInfo(3),
+    Step::DeviceInfo(4),
+    Step::DeviceInfo(5),
+    Step::ExitConfig,
+];
+/// Rest of a job failed in config mode
+const ABORT: &[Step] = &[Step::ExitConfig];
+
+/// Time `at_ms` is reached by wrapping clock
+pub(crate) fn is_due(now_ms: u32, at_ms: u32) -> bool {
+    (now_ms.wrapping_sub(at_ms) as i32) >= 0
+}
+
+/// Non-blocking driver of [`Controller`], `tick` is called from the main loop
+/// with time of a monotonic clock. Polls are sent at fixed rate, sequences of config mode
+/// send one packet per tick and the pauses between packets are waited by the clock, not by sleeps
+pub struct PollScheduler<Dev: PsxTrasferData> {
+    controller: Controller<PsxDriver<Dev>>,
+    interval_ms: u32,
+    poll_at_ms: u32,
+    step_at_ms: u32,
+    job: &'static [Step],
+    cursor: usize,
+    is_info_requested: bool,
+}
+
+impl<Dev: PsxTrasferData> PollScheduler<Dev> {
+    pub fn new(controller: Controller<PsxDriver<Dev>>) -> Self {
+        Self {
+            controller,
+            interval_ms: 1000 / POLL_RATE_HZ,
+            poll_at_ms: 0,
+            step_at_ms: 0,
+            job: &[],
+            cursor: 0,
+            is_info_requested: false,
+        }
+    }
+    /// Polls per second
+    pub fn set_rate(&mut self, rate_hz: u32) {
+        self.interval_ms = 1000 / rate_hz.clamp(1, 1000);
+    }
+    pub fn controller(&self) -> &Controller<PsxDriver<Dev>> {
+        &self.controller
+    }
+    /// Settings of controller are applied by the scheduler, blocking methods
+    /// (`connect`, `poll`) should not be used
+    pub fn controller_mut(&mut self) -> &mut Controller<PsxDriver<Dev>> {
+        &mut self.controller
+    }
+    pub fn release(self) -> Controller<PsxDriver<Dev>> {
+        self.controller
+    }
+    /// Read constants of device to `Controller::info` between polls
+    pub fn request_device_info(&mut self) {
+        self.is_info_requested = true;
+    }
+    /// Sequence of config mode is in progress
+    pub fn is_busy(&self) -> bool {
+        self.cursor < self.job.len()
+    }
+    /// Run work due at `now_ms`, returns true if device was polled
+    pub fn tick(&mut self, now_ms: u32) -> bool {
+        let elapsed_ms = self.controller.advance_time(now_ms);
+        self.controller.tick_rumble(elapsed_ms);
+        self.run(now_ms)
+    }
+
+    fn run(&mut self, now_ms: u32) -> bool {
+        if self.is_busy() {
+            if is_due(now_ms, self.step_at_ms) {
+                self.run_job(now_ms);
+            }
+            return false;
+        }
+        match self.controller.state {
+            DeviceState::Connected if self.controller.is_config_pending() => {
+                self.start(SETUP, now_ms)
+            }
+            DeviceState::Connected if self.is_info_requested => {
+                self.is_info_requested = false;
+                self.start(DEVICE_INFO, now_ms);
+            }
+            DeviceState::Connected if is_due(now_ms, self.poll_at_ms) => {
+                return self.poll(now_ms);
+            }
+            DeviceState::Connected => {}
+            _ if self.controller.is_probe_due(now_ms) => self.start(CONNECT, now_ms),
+            _ => {}
+        }
+        false
+    }
+    fn start(&mut self, job: &'static [Step], now_ms: u32) {
+        self.job = job;
+        self.cursor = 0;
+        self.run_job(now_ms);
+    }
+    /// Pause between packets of config mode, longer for models with quirks
+    fn gap_ms(&self) -> u32 {
+        let extra_ns = self.controller.quirks().extra_sleep_ns;
+        Dev::SLEEP_NS.saturating_add(extra_ns).div_ceil(1_000_000)
+    }
+    /// Run steps until a packet is sent, the next step waits for the pause
+    fn run_job(&mut self, now_ms: u32) {
+        while self.is_busy() {
+            let step = self.job[self.cursor];
+            self.cursor += 1;
+            match self.controller.run_step(step) {
+                StepResult::Sent => {
+                    self.step_at_ms = now_ms.wrapping_add(self.gap_ms());
+                    self.poll_at_ms = self.step_at_ms;
+                    return;
+                }
+                StepResult::Skipped => {}
+                StepResult::Failed => {
+                    self.abort();
+                    self.step_at_ms = now_ms.wrapping_add(self.gap_ms());
+                    return;
+                }
+            }
+        }
+    }
+    /// Drop the rest of the job, config mode entered by the job is left after the pause
+    fn abort(&mut self) {
+        let (done, rest) = self.job.split_at(self.cursor);
+        let is_entered = done.iter().any(|s| matches!(s, Step::EnterConfig));
+        let is_exit_pending = rest.iter().any(|s| matches!(s, Step::ExitConfig));
+        match is_entered && is_exit_pending {
+            true => self.job = ABORT,
+            false => self.job = &[],
+        }
+        self.cursor = 0;
+    }
+    /// Fixed rate polling, the schedule is restarted if it is late more than an interval
+    fn poll(&mut self, now_ms: u32) -> bool {
+        self.poll_at_ms = self.poll_at_ms.wrapping_add(self.interval_ms);
+        if is_due(now_ms, self.poll_at_ms) {
+            self.poll_at_ms = now_ms.wrapping_add(self.interval_ms);
+        }
+        matches!(self.controller.run_step(Step::Poll), StepResult::Sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::DeviceError;
+    use crate::fingerprint::DeviceModel;
+    use crate::mock::{create_mock_controller, MockDevice, MockModel, MockPad};
+    use embedded_hal::digital::ErrorKind;
+
+    fn run(scheduler: &mut PollScheduler<MockDevice<'_>>, from_ms: u32, to_ms: u32) -> u32 {
+        (from_ms..to_ms).filter(|t| scheduler.tick(*t)).count() as u32
+    }
+
+    #[test]
+    fn polls_at_fixed_rate() {
+        let pad = MockPad::dualshock2();
+        let mut scheduler = PollScheduler::new(create_mock_controller(&pad));
+        run(&mut scheduler, 0, 200);
+        assert_eq!(DeviceState::Connected, scheduler.controller().state);
+        // 60 Hz, 16 ms interval
+        let polls = run(&mut scheduler, 200, 1200);
+        assert!((62..=63).contains(&polls), "{polls}");
+        // pauses are waited by the clock, only the mock counts them
+        assert!(pad.elapsed_ns() < 8_000_000);
+    }
+
+    #[test]
+    fn wireless_gap_is_longer() {
+        let pad = MockPad::dualshock2();
+        let mut scheduler = PollScheduler::new(create_mock_controller(&pad));
+        run(&mut scheduler, 0, 400);
+        assert_eq!(8, scheduler.gap_ms());
+
+        let pad = MockPad::new(MockModel::Wireless);
+        let mut scheduler = PollScheduler::new(create_mock_controller(&pad));
+        run(&mut scheduler, 0, 400);
+        assert_eq!(DeviceModel::Wireless, scheduler.controller().model);
+        assert_eq!(12, scheduler.gap_ms());
+    }
+
+    #[test]
+    fn failed_job_exits_config() {
+        let pad = MockPad::dualshock2();
+        let mut scheduler = PollScheduler::new(create_mock_controller(&pad));
+        let mut now = 0;
+        while !pad.is_config() {
+            scheduler.tick(now);
+            now += 1;
+        }
+        // the next step of CONNECT fails
+        pad.set_fault(Some(DeviceError::Pin(ErrorKind::Other)));
+        let cursor = scheduler.cursor;
+        while scheduler.cursor == cursor {
+            scheduler.tick(now);
+            now += 1;
+        }
+        pad.set_fault(None);
+        assert!(scheduler.is_busy() && pad.is_config());
+        while scheduler.is_busy() {
+            scheduler.tick(now);
+            now += 1;
+        }
+        assert!(!pad.is_config());
+        assert_ne!(DeviceState::Connected, scheduler.controller().state);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::buttons::{AnalogSticks, Button, ButtonPressures, ButtonSet};
+use crate::commands::{DeviceMode, DeviceType};
+
+/// State of the gamepad at one poll, a copy detached from [`Controller`](crate::Controller)
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GamepadSnapshot {
+    buttons: ButtonSet,
+    buttons_prev: ButtonSet,
+    sticks: AnalogSticks,
+    pressures: Option<ButtonPressures>,
+    mode: DeviceMode,
+    ctype: DeviceType,
+    timestamp_ms: u32,
+}
+
+impl GamepadSnapshot {
+    pub(crate) fn new(
+        buttons: ButtonSet,
+        buttons_prev: ButtonSet,
+        sticks: AnalogSticks,
+        pressures: Option<ButtonPressures>,
+        mode: DeviceMode,
+        ctype: DeviceType,
+        timestamp_ms: u32,
+    ) -> Self {
+        Self {
+            buttons,
+            buttons_prev,
+            sticks,
+            pressures,
+            mode,
+            ctype,
+            timestamp_ms,
+        }
+    }
+    /// Pressed buttons
+    pub fn buttons(&self) -> ButtonSet {
+        self.buttons
+    }
+    /// Pressed buttons of the previous poll
+    pub fn previous_buttons(&self) -> ButtonSet {
+        self.buttons_prev
+    }
+    pub fn sticks(&self) -> AnalogSticks {
+        self.sticks
+    }
+    /// Pressures of buttons, only in the pressures mode of DualShock 2
+    pub fn pressures(&self) -> Option<ButtonPressures> {
+        self.pressures
+    }
+    pub fn mode(&self) -> DeviceMode {
+        self.mode
+    }
+    pub fn device_type(&self) -> DeviceType {
+        self.ctype
+    }
+    /// Time of the poll in ms
+    pub fn timestamp_ms(&self) -> u32 {
+        self.timestamp_ms
+    }
+    pub fn is_pressed(&self, btn: Button) -> bool {
+        self.buttons.contains(btn)
+    }
+    /// Button changed from up to down
+    pub fn is_down(&self, btn: Button) -> bool {
+        self.buttons.contains(btn) && !self.buttons_prev.contains(btn)
+    }
+    /// Button changed from down to up
+    pub fn is_up(&self, btn: Button) -> bool {
+        !self.buttons.contains(btn) && self.buttons_prev.contains(btn)
+    }
+    /// Buttons changed since the previous poll
+    pub fn changed(&self) -> ButtonSet {
+        self.buttons ^ self.buttons_prev
+    }
+}
@@ -0,0 +1,251 @@
+use crate::buttons::AnalogSticks;
+
+/// Full scale of normalized values
+const FULL: i32 = i16::MAX as i32;
+/// Maximum distance of a calibrated center from 128, farther stick is deflected by user
+const MAX_CENTER_OFFSET: u8 = 48;
+
+/// Response curve applied to the deflection of stick
+#[derive(Copy, Clone)]
+pub enum ResponseCurve {
+    Linear,
+    Quadratic,
+    /// Custom table of output (0 - 255) for equal steps of deflection, at least 2 points
+    Lut(&'static [u8]),
+}
+
+impl ResponseCurve {
+    /// Map deflection 0..=FULL to output 0..=FULL
+    fn apply(&self, value: i32) -> i32 {
+        match self {
+            Self::Linear => value,
+            Self::Quadratic => value * value / FULL,
+            Self::Lut(lut) if lut.len() >= 2 => {
+                let steps = (lut.len() - 1) as i32;
+                let pos = value * steps;
+                let i = (pos / FULL).min(steps - 1) as usize;
+                let frac = pos - i as i32 * FULL;
+                let (a, b) = (lut[i] as i32, lut[i + 1] as i32);
+                (a * FULL + (b - a) * frac) / 255
+            }
+            Self::Lut(_) => value,
+        }
+    }
+}
+
+/// Normalized sticks, Q15 fixed point -32767..=32767 (-1.0..=1.0).
+/// Axes keep orientation of device: up and left are negative
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct ProcessedSticks {
+    pub lx: i16,
+    pub ly: i16,
+    pub rx: i16,
+    pub ry: i16,
+}
+impl ProcessedSticks {
+    /// Values scaled to -127..=127 in order lx, ly, rx, ry
+    pub fn to_i8(&self) -> [i8; 4] {
+        [self.lx, self.ly, self.rx, self.ry].map(|v| (v as i32 * 127 / FULL) as i8)
+    }
+}
+
+/// Calibration, deadzones and response curve of analog sticks.
+/// Deadzones and outer saturation are distances from center in raw units (0 - 128)
+#[derive(Copy, Clone)]
+pub struct StickProcessor {
+    /// Rest position of every axis
+    pub center: AnalogSticks,
+    /// Each axis below this value is zero
+    pub axial_deadzone: u8,
+    /// Stick inside this circle is zero
+    pub radial_deadzone: u8,
+    /// Deflection above this value is full scale
+    pub outer: u8,
+    pub curve: ResponseCurve,
+}
+
+impl Default for StickProcessor {
+    fn default() -> Self {
+        Self {
+            center: AnalogSticks::default(),
+            axial_deadzone: 0,
+            radial_deadzone: 10,
+            outer: 120,
+            curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl StickProcessor {
+    pub fn new(axial_deadzone: u8, radial_deadzone: u8, outer: u8, curve: ResponseCurve) -> Self {
+        Self {
+            center: AnalogSticks::default(),
+            axial_deadzone,
+            radial_deadzone,
+            outer,
+            curve,
+        }
+    }
+    /// Use sticks at rest as center, returns false if the sticks are too far from 128
+    pub fn calibrate(&mut self, sticks: AnalogSticks) -> bool {
+        let near = |v: u8| v.abs_diff(128) <= MAX_CENTER_OFFSET;
+        if near(sticks.lx) && near(sticks.ly) && near(sticks.rx) && near(sticks.ry) {
+            self.center = sticks;
+            true
+        } else {
+            false
+        }
+    }
+    pub fn process(&self, sticks: AnalogSticks) -> ProcessedSticks {
+        let c = &self.center;
+        let (lx, ly) = self.process_stick(sticks.lx, sticks.ly, c.lx, c.ly);
+        let (rx, ry) = self.process_stick(sticks.rx, sticks.ry, c.rx, c.ry);
+        ProcessedSticks { lx, ly, rx, ry }
+    }
+    fn process_stick(&self, x: u8, y: u8, cx: u8, cy: u8) -> (i16, i16) {
+        let axial = Self::raw_to_q15(self.axial_deadzone);
+        let x = Self::axial(Self::normalize(x, cx), axial);
+        let y = Self::axial(Self::normalize(y, cy), axial);
+        let magnitude = isqrt((x * x) as u32 + (y * y) as u32) as i32;
+        let inner = Self::raw_to_q15(self.radial_deadzone);
+        let outer = Self::raw_to_q15(self.outer).max(inner + 1);
+        if magnitude <= inner {
+            return (0, 0);
+        }
+        let scaled = ((magnitude - inner) * FULL / (outer - inner)).min(FULL);
+        let out = self.curve.apply(scaled);
+        let clamp = |v: i32| (v * out / magnitude).clamp(-FULL, FULL) as i16;
+        (clamp(x), clamp(y))
+    }
+    /// Signed deflection from center, each side is scaled to its own range
+    fn normalize(value: u8, center: u8) -> i32 {
+        let delta = value as i32 - center as i32;
+        let range = match delta > 0 {
+            true => 255 - center as i32,
+            false => center as i32,
+        };
+        match range {
+            0 => 0,
+            _ => delta * FULL / range,
+        }
+    }
+    fn axial(value: i32, deadzone: i32) -> i32 {
+        if value.abs() <= deadzone {
+            return 0;
+        }
+        let scaled = (value.abs() - deadzone) * FULL / (FULL - deadzone).max(1);
+        scaled * value.signum()
+    }
+    fn raw_to_q15(raw: u8) -> i32 {
+        (raw as i32 * FULL / 128).min(FULL)
+    }
+}
+
+/// Integer square root
+fn isqrt(n: u32) -> u32 {
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn left(processor: &StickProcessor, lx: u8, ly: u8) -> (i16, i16) {
+        let out = processor.process(AnalogSticks::new(lx, ly, 128, 128));
+        (out.lx, out.ly)
+    }
+
+    #[test]
+    fn radial_deadzone() {
+        let processor = StickProcessor::default();
+        assert_eq!(
+            ProcessedSticks::default(),
+            processor.process(AnalogSticks::default())
+        );
+        // 10 of 128 is the edge of the circle
+        assert_eq!((0, 0), left(&processor, 137, 128));
+        assert_eq!((0, 0), left(&processor, 128, 119));
+        assert!(left(&processor, 139, 128).0 > 0);
+        assert!(left(&processor, 128, 117).1 < 0);
+    }
+
+    #[test]
+    fn axial_deadzone() {
+        let processor = StickProcessor::new(20, 0, 128, ResponseCurve::Linear);
+        // small X is dropped from a stick pushed down
+        let (x, y) = left(&processor, 143, 255);
+        assert_eq!((0, FULL as i16), (x, y));
+        assert!(left(&processor, 150, 255).0 > 0);
+    }
+
+    #[test]
+    fn outer_saturation() {
+        let processor = StickProcessor::default();
+        assert_eq!((FULL as i16, 0), left(&processor, 255, 128));
+        assert_eq!((-FULL as i16, 0), left(&processor, 0, 128));
+        // 120 of 128 is full scale
+        assert_eq!((FULL as i16, 0), left(&processor, 248, 128));
+        assert!(left(&processor, 240, 128).0 < FULL as i16);
+    }
+
+    #[test]
+    fn curves() {
+        let linear = StickProcessor::new(0, 0, 128, ResponseCurve::Linear);
+        let quadratic = StickProcessor {
+            curve: ResponseCurve::Quadratic,
+            ..linear
+        };
+        assert_eq!((-16383, 0), left(&linear, 64, 128));
+        assert_eq!((-8191, 0), left(&quadratic, 64, 128));
+        assert_eq!((-FULL as i16, 0), left(&quadratic, 0, 128));
+
+        let lut = StickProcessor {
+            curve: ResponseCurve::Lut(&[0, 0, 255]),
+            ..linear
+        };
+        assert_eq!((0, 0), left(&lut, 64, 128));
+        assert_eq!((-FULL as i16, 0), left(&lut, 0, 128));
+        // too short table is linear
+        let short = StickProcessor {
+            curve: ResponseCurve::Lut(&[255]),
+            ..linear
+        };
+        assert_eq!(left(&linear, 64, 128), left(&short, 64, 128));
+    }
+
+    #[test]
+    fn calibration() {
+        let mut processor = StickProcessor::default();
+        assert!(!processor.calibrate(AnalogSticks::new(128, 128, 128, 60)));
+        assert!(processor.calibrate(AnalogSticks::new(100, 140, 128, 128)));
+        assert_eq!((0, 0), left(&processor, 100, 140));
+        // each side of the center is scaled to its own range
+        assert_eq!((-FULL as i16, 0), left(&processor, 0, 140));
+        assert_eq!((FULL as i16, 0), left(&processor, 255, 140));
+    }
+
+    #[test]
+    fn to_i8() {
+        let sticks = ProcessedSticks {
+            lx: i16::MAX,
+            ly: -i16::MAX,
+            rx: 0,
+            ry: 16384,
+        };
+        assert_eq!([127, -127, 0, 63], sticks.to_i8());
+    }
+
+    #[test]
+    fn integer_sqrt() {
+        let list = [(0, 0), (1, 1), (15, 3), (16, 4), (17, 4), (u32::MAX, 65535)];
+        for (n, root) in list {
+            assert_eq!(root, isqrt(n), "{n}");
+        }
+    }
+}
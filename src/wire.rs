@@ -0,0 +1,516 @@
+//! Compact binary format for streaming gamepad state over UART or radio.
+//!
+//! A frame is `SYNC, VERSION << 4 | KIND, SEQ, LEN, TIMESTAMP[4], PAYLOAD[LEN], CRC8`.
+//! The state follows the layout of the poll response in `DeviceBuffer`: ID byte
+//! (mode and word count), device type in place of 0x5A, buttons, sticks and pressures.
+//! The word count of ID defines which parts are sent: 1 - buttons, 3 - sticks, 9 - pressures.
+//!
+//! A full frame carries the state, a delta frame carries a mask of changed bytes
+//! and their values against the frame with previous `SEQ`. [`Decoder`] skips deltas
+//! until the next full frame if a frame was lost or corrupted.
+use crate::buttons::{AnalogSticks, ButtonPressures, ButtonSet};
+use crate::commands::{DeviceMode, DeviceType};
+use crate::snapshot::GamepadSnapshot;
+
+/// First byte of a frame
+pub const SYNC: u8 = 0xA5;
+/// Version of the format
+pub const VERSION: u8 = 1;
+/// SYNC, version and kind, SEQ, LEN, timestamp
+pub const HEADER_SIZE: usize = 8;
+/// ID, type, buttons, sticks and pressures
+pub const STATE_SIZE: usize = 20;
+/// Bytes of the mask of changed bytes in a delta frame
+const MASK_SIZE: usize = 3;
+pub const MAX_FRAME_SIZE: usize = HEADER_SIZE + MASK_SIZE + STATE_SIZE + 1;
+/// Every n-th frame is full, the decoder resyncs on it
+const KEYFRAME_INTERVAL: u8 = 16;
+
+const KIND_FULL: u8 = 0x00;
+const KIND_DELTA: u8 = 0x01;
+
+/// Layout of the state, offsets of `DeviceBuffer` data minus one
+const ID: usize = 0;
+const TYPE: usize = 1;
+const BUTTONS: usize = 2;
+const STICKS: usize = 4;
+const PRESSURES: usize = 8;
+
+/// Words of the response: buttons, sticks, pressures
+const WORDS_DIGITAL: u8 = 1;
+const WORDS_ANALOG: u8 = 3;
+const WORDS_PRESSURES: u8 = 9;
+
+/// CRC-8, polynomial 0x07
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x07,
+        })
+    })
+}
+
+fn to_state(snapshot: &GamepadSnapshot) -> [u8; STATE_SIZE] {
+    let mut state = [0u8; STATE_SIZE];
+    let words = match (snapshot.pressures(), snapshot.mode()) {
+        (Some(_), _) => WORDS_PRESSURES,
+        (None, DeviceMode::Digital) => WORDS_DIGITAL,
+        (None, _) => WORDS_ANALOG,
+    };
+    state[ID] = snapshot.mode() as u8 | words;
+    state[TYPE] = snapshot.device_type() as u8;
+    // buttons are active low as in the response
+    let buttons = !snapshot.buttons().bits();
+    state[BUTTONS..STICKS].copy_from_slice(&buttons.to_le_bytes());
+    let sticks = snapshot.sticks();
+    state[STICKS..PRESSURES].copy_from_slice(&[sticks.rx, sticks.ry, sticks.lx, sticks.ly]);
+    if let Some(pressures) = snapshot.pressures() {
+        state[PRESSURES..].copy_from_slice(pressures.as_bytes());
+    }
+    // parts above the word count are not sent, deltas should not see them
+    let len = state_len(&state);
+    state[len..].fill(0);
+    state
+}
+
+/// Bytes of the state used by its word count
+fn state_len(state: &[u8; STATE_SIZE]) -> usize {
+    (BUTTONS + 2 * (state[ID] & 0x0F) as usize).min(STATE_SIZE)
+}
+
+fn device_type(v: u8) -> DeviceType {
+    match v {
+        v if v == DeviceType::GuitarHero as u8 => DeviceType::GuitarHero,
+        v => DeviceType::from(v),
+    }
+}
+
+/// Encoder of snapshots to frames, the last state is the base of delta frames
+pub struct Encoder {
+    prev: Option<[u8; STATE_SIZE]>,
+    seq: u8,
+    keyframe_interval: u8,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {
+            prev: None,
+            seq: 0,
+            keyframe_interval: KEYFRAME_INTERVAL,
+        }
+    }
+    /// Send a full frame every `interval` frames, 1 - delta frames are disabled
+    pub fn set_keyframe_interval(&mut self, interval: u8) {
+        self.keyframe_interval = interval.max(1);
+    }
+    /// The next frame is full
+    pub fn reset(&mut self) {
+        self.prev = None;
+    }
+    /// Write frame of the snapshot to `out`, returns size of the frame
+    pub fn encode(&mut self, snapshot: &GamepadSnapshot, out: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        let state = to_state(snapshot);
+        let is_keyframe = self.seq % self.keyframe_interval == 0;
+        let (kind, len) = match self.prev {
+            Some(prev) if !is_keyframe => (KIND_DELTA, Self::write_delta(&prev, &state, out)),
+            _ => {
+                let len = state_len(&state);
+                out[HEADER_SIZE..HEADER_SIZE + len].copy_from_slice(&state[..len]);
+                (KIND_FULL, len)
+            }
+        };
+        out[0] = SYNC;
+        out[1] = (VERSION << 4) | kind;
+        out[2] = self.seq;
+        out[3] = len as u8;
+        out[4..HEADER_SIZE].copy_from_slice(&snapshot.timestamp_ms().to_le_bytes());
+        let end = HEADER_SIZE + len;
+        out[end] = crc8(&out[1..end]);
+        self.prev = Some(state);
+        self.seq = self.seq.wrapping_add(1);
+        end + 1
+    }
+    /// Mask of changed bytes and their values
+    fn write_delta(
+        prev: &[u8; STATE_SIZE],
+        state: &[u8; STATE_SIZE],
+        out: &mut [u8; MAX_FRAME_SIZE],
+    ) -> usize {
+        let mut mask = 0u32;
+        let mut len = MASK_SIZE;
+        for (i, (old, new)) in prev.iter().zip(state.iter()).enumerate() {
+            if old != new {
+                mask |= 1 << i;
+                out[HEADER_SIZE + len] = *new;
+                len += 1;
+            }
+        }
+        out[HEADER_SIZE..HEADER_SIZE + MASK_SIZE].copy_from_slice(&mask.to_le_bytes()[..MASK_SIZE]);
+        len
+    }
+}
+
+/// Result of the bytes received so far
+enum Check {
+    Incomplete,
+    Ready,
+    Bad,
+}
+
+/// Streaming decoder, bytes are pushed as they arrive
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_SIZE],
+    len: usize,
+    state: Option<[u8; STATE_SIZE]>,
+    seq: u8,
+    errors: u32,
+    skipped: u32,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            buf: [0u8; MAX_FRAME_SIZE],
+            len: 0,
+            state: None,
+            seq: 0,
+            errors: 0,
+            skipped: 0,
+        }
+    }
+    /// Frames dropped for bad CRC, version or length
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+    /// Delta frames dropped because their base was lost
+    pub fn skipped(&self) -> u32 {
+        self.skipped
+    }
+    /// Add received byte, returns the snapshot when a frame is complete
+    pub fn push(&mut self, byte: u8) -> Option<GamepadSnapshot> {
+        if self.len == 0 && byte != SYNC {
+            return None;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        match self.check() {
+            Check::Incomplete => None,
+            Check::Ready => {
+                let snapshot = self.decode();
+                self.len = 0;
+                snapshot
+            }
+            Check::Bad => {
+                self.errors += 1;
+                self.resync()
+            }
+        }
+    }
+    /// Add received bytes, returns the last decoded snapshot
+    pub fn push_slice(&mut self, data: &[u8]) -> Option<GamepadSnapshot> {
+        data.iter()
+            .fold(None, |last, byte| self.push(*byte).or(last))
+    }
+
+    fn check(&self) -> Check {
+        if self.len < HEADER_SIZE {
+            return match self.len > 1 && self.buf[1] >> 4 != VERSION {
+                true => Check::Bad,
+                false => Check::Incomplete,
+            };
+        }
+        let kind = self.buf[1] & 0x0F;
+        let len = self.buf[3] as usize;
+        let (min_len, max_len) = match kind {
+            KIND_FULL => (BUTTONS, STATE_SIZE),
+            KIND_DELTA => (MASK_SIZE, MASK_SIZE + STATE_SIZE),
+            _ => return Check::Bad,
+        };
+        if len < min_len || len > max_len {
+            return Check::Bad;
+        }
+        let end = HEADER_SIZE + len;
+        match self.len {
+            n if n <= end => Check::Incomplete,
+            _ if crc8(&self.buf[1..end]) != self.buf[end] => Check::Bad,
+            _ => Check::Ready,
+        }
+    }
+    /// Drop the SYNC of the bad frame and parse the bytes after it again,
+    /// they may hold the next frame and the start of one more
+    fn resync(&mut self) -> Option<GamepadSnapshot> {
+        let (buf, len) = (self.buf, self.len);
+        self.len = 0;
+        self.push_slice(&buf[1..len])
+    }
+    fn decode(&mut self) -> Option<GamepadSnapshot> {
+        let seq = self.buf[2];
+        let len = self.buf[3] as usize;
+        let payload = &self.buf[HEADER_SIZE..HEADER_SIZE + len];
+        let prev = self.state;
+        let state = match self.buf[1] & 0x0F {
+            KIND_FULL => {
+                let mut state = [0u8; STATE_SIZE];
+                state[..len].copy_from_slice(payload);
+                state
+            }
+            _ => match prev {
+                Some(mut state) if seq == self.seq.wrapping_add(1) => {
+                    let mut mask = [0u8; 4];
+                    mask[..MASK_SIZE].copy_from_slice(&payload[..MASK_SIZE]);
+                    let mask = u32::from_le_bytes(mask);
+                    let mut values = payload[MASK_SIZE..].iter();
+                    for (i, byte) in state.iter_mut().enumerate() {
+                        if mask & (1 << i) != 0 {
+                            *byte = *values.next()?;
+                        }
+                    }
+                    state
+                }
+                _ => {
+                    // the base is lost, wait for a full frame
+                    self.state = None;
+                    self.skipped += 1;
+                    return None;
+                }
+            },
+        };
+        self.state = Some(state);
+        self.seq = seq;
+        let timestamp_ms = u32::from_le_bytes([self.buf[4], self.buf[5], self.buf[6], self.buf[7]]);
+        Some(Self::to_snapshot(&state, prev.as_ref(), timestamp_ms))
+    }
+    fn to_snapshot(
+        state: &[u8; STATE_SIZE],
+        prev: Option<&[u8; STATE_SIZE]>,
+        timestamp_ms: u32,
+    ) -> GamepadSnapshot {
+        let buttons = |s: &[u8; STATE_SIZE]| {
+            ButtonSet::from_active_low(u16::from_le_bytes([s[BUTTONS], s[BUTTONS + 1]]))
+        };
+        let words = state[ID] & 0x0F;
+        let s = &state[STICKS..PRESSURES];
+        let sticks = match words >= WORDS_ANALOG {
+            true => AnalogSticks::new(s[2], s[3], s[0], s[1]),
+            false => AnalogSticks::default(),
+        };
+        let pressures = (words >= WORDS_PRESSURES).then(|| {
+            let mut data = [0u8; 12];
+            data.copy_from_slice(&state[PRESSURES..]);
+            ButtonPressures::new(data)
+        });
+        GamepadSnapshot::new(
+            buttons(state),
+            prev.map_or(ButtonSet::EMPTY, buttons),
+            sticks,
+            pressures,
+            DeviceMode::from(state[ID]),
+            device_type(state[TYPE]),
+            timestamp_ms,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::Button;
+
+    fn snapshot(
+        buttons: ButtonSet,
+        sticks: AnalogSticks,
+        pressures: Option<ButtonPressures>,
+        timestamp_ms: u32,
+    ) -> GamepadSnapshot {
+        GamepadSnapshot::new(
+            buttons,
+            ButtonSet::EMPTY,
+            sticks,
+            pressures,
+            DeviceMode::Analog,
+            DeviceType::DualShock2,
+            timestamp_ms,
+        )
+    }
+
+    fn encode(
+        encoder: &mut Encoder,
+        pressures: Option<ButtonPressures>,
+        timestamp_ms: u32,
+    ) -> ([u8; MAX_FRAME_SIZE], usize) {
+        let snapshot = snapshot(
+            ButtonSet::EMPTY,
+            AnalogSticks::default(),
+            pressures,
+            timestamp_ms,
+        );
+        encode_snapshot(encoder, &snapshot)
+    }
+
+    fn encode_snapshot(
+        encoder: &mut Encoder,
+        snapshot: &GamepadSnapshot,
+    ) -> ([u8; MAX_FRAME_SIZE], usize) {
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        let len = encoder.encode(snapshot, &mut frame);
+        (frame, len)
+    }
+
+    #[test]
+    fn full_frame_round_trip() {
+        let mut pressures = [0u8; 12];
+        pressures[3] = 0x80;
+        let sent = snapshot(
+            Button::Cross | Button::Up,
+            AnalogSticks::new(0x10, 0x20, 0x30, 0x40),
+            Some(ButtonPressures::new(pressures)),
+            1234,
+        );
+        let (frame, len) = encode_snapshot(&mut Encoder::new(), &sent);
+        assert_eq!(HEADER_SIZE + STATE_SIZE + 1, len);
+        assert_eq!(
+            [SYNC, VERSION << 4 | KIND_FULL, 0, STATE_SIZE as u8],
+            frame[..4]
+        );
+        assert_eq!(0x79, frame[HEADER_SIZE + ID]);
+
+        let mut decoder = Decoder::new();
+        assert_eq!(None, decoder.push_slice(&frame[..len - 1]));
+        assert_eq!(Some(sent), decoder.push(frame[len - 1]));
+
+        // digital state sends buttons only
+        let sent = GamepadSnapshot::new(
+            Button::Start.into(),
+            ButtonSet::EMPTY,
+            AnalogSticks::default(),
+            None,
+            DeviceMode::Digital,
+            DeviceType::DualShock1,
+            0,
+        );
+        let (frame, len) = encode_snapshot(&mut Encoder::new(), &sent);
+        assert_eq!(HEADER_SIZE + 4 + 1, len);
+        assert_eq!(Some(sent), Decoder::new().push_slice(&frame[..len]));
+    }
+
+    #[test]
+    fn delta_frame() {
+        let sticks = AnalogSticks::new(0x80, 0x80, 0x80, 0x80);
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+        let first = snapshot(Button::Cross.into(), sticks, None, 0);
+        let (frame, len) = encode_snapshot(&mut encoder, &first);
+        decoder.push_slice(&frame[..len]);
+
+        let moved = AnalogSticks { lx: 0x00, ..sticks };
+        let second = snapshot(Button::Cross | Button::Circle, moved, None, 16);
+        let (frame, len) = encode_snapshot(&mut encoder, &second);
+        assert_eq!(VERSION << 4 | KIND_DELTA, frame[1]);
+        // mask, high byte of buttons and LX
+        assert_eq!(MASK_SIZE + 2, frame[3] as usize);
+        assert_eq!(
+            [0x48, 0x00, 0x00],
+            frame[HEADER_SIZE..HEADER_SIZE + MASK_SIZE]
+        );
+
+        let decoded = decoder.push_slice(&frame[..len]).unwrap();
+        assert_eq!(Button::Cross | Button::Circle, decoded.buttons());
+        assert_eq!(moved, decoded.sticks());
+        assert!(decoded.is_down(Button::Circle) && !decoded.is_down(Button::Cross));
+        assert_eq!(16, decoded.timestamp_ms());
+    }
+
+    #[test]
+    fn keyframe_every_16_frames() {
+        let mut encoder = Encoder::new();
+        let kinds: [u8; 33] = core::array::from_fn(|i| {
+            let (frame, _) = encode(&mut encoder, None, i as u32);
+            frame[1] & 0x0F
+        });
+        for (i, kind) in kinds.iter().enumerate() {
+            let expected = match i % 16 {
+                0 => KIND_FULL,
+                _ => KIND_DELTA,
+            };
+            assert_eq!(expected, *kind, "frame {i}");
+        }
+        // reset forces a full frame
+        encoder.reset();
+        assert_eq!(KIND_FULL, encode(&mut encoder, None, 0).0[1] & 0x0F);
+    }
+
+    #[test]
+    fn crc_mismatch() {
+        let mut encoder = Encoder::new();
+        let (mut frame, len) = encode(&mut encoder, None, 7);
+        frame[HEADER_SIZE] ^= 0x01;
+        let mut decoder = Decoder::new();
+        assert_eq!(None, decoder.push_slice(&frame[..len]));
+        assert_eq!(1, decoder.errors());
+
+        frame[HEADER_SIZE] ^= 0x01;
+        assert_eq!(7, decoder.push_slice(&frame[..len]).unwrap().timestamp_ms());
+    }
+
+    #[test]
+    fn sequence_gap_waits_for_keyframe() {
+        let mut encoder = Encoder::new();
+        let frames: [([u8; MAX_FRAME_SIZE], usize); 18] =
+            core::array::from_fn(|i| encode(&mut encoder, None, i as u32));
+        let mut decoder = Decoder::new();
+        decoder.push_slice(&frames[0].0[..frames[0].1]);
+        decoder.push_slice(&frames[1].0[..frames[1].1]);
+        // frame 2 is lost, deltas are skipped until the full frame 16
+        let received: [Option<u32>; 15] = core::array::from_fn(|i| {
+            let (frame, len) = &frames[i + 3];
+            decoder.push_slice(&frame[..*len]).map(|s| s.timestamp_ms())
+        });
+        assert!(received[..13].iter().all(Option::is_none));
+        assert_eq!([Some(16), Some(17)], received[13..]);
+        assert_eq!((13, 0), (decoder.skipped(), decoder.errors()));
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let (mut frame, len) = encode(&mut Encoder::new(), None, 0);
+        frame[1] = (VERSION + 1) << 4 | KIND_FULL;
+        frame[len - 1] = crc8(&frame[1..len - 1]);
+        let mut decoder = Decoder::new();
+        assert_eq!(None, decoder.push_slice(&frame[..len]));
+        assert_eq!(1, decoder.errors());
+    }
+    #[test]
+    fn resync_parses_rest_of_chunk() {
+        let mut encoder = Encoder::new();
+        encoder.set_keyframe_interval(1);
+        let (cut, _) = encode(&mut encoder, Some(ButtonPressures::new([0; 12])), 0);
+        let (f1, n1) = encode(&mut encoder, None, 16);
+        let (f2, n2) = encode(&mut encoder, None, 32);
+        // the cut frame declares a payload longer than both frames after it
+        let mut decoder = Decoder::new();
+        let mut timestamps = [0u32; 2];
+        let mut count = 0;
+        for byte in cut[..HEADER_SIZE].iter().chain(&f1[..n1]).chain(&f2[..n2]) {
+            if let Some(snapshot) = decoder.push(*byte) {
+                timestamps[count] = snapshot.timestamp_ms();
+                count += 1;
+            }
+        }
+        assert_eq!(1, decoder.errors());
+        assert_eq!((2, [16, 32]), (count, timestamps));
+    }
+}